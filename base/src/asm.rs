@@ -0,0 +1,413 @@
+//! Text format for instruction programs.
+//!
+//! One instruction per line, named after the helpers in
+//! [`instructions`](crate::papervm::instructions). Words are written as
+//! `(x, y, len)`, orderings as `<`, `=` or `>`. Subprograms for `call` and
+//! `fork` are defined by name in `prog <name>` ... `end` blocks, everything
+//! outside such a block is the main program:
+//!
+//! ```text
+//! prog add
+//!     write "\n"
+//!     add (0, -1, 10) (10, -1, 10)
+//!     circle (-10, 0, 10)
+//! end
+//!
+//! write 1.
+//! write 2.
+//! call add (-20, 0, 10) (-10, 0, 10)
+//! circle (-10, 0, 10)
+//! ```
+//!
+//! `fork` takes several calls separated by `;`. Conditions of
+//! `jump_rel_if_cond` are written in prefix form, for example
+//! `jump_rel_if_cond and not empty (0, -1, 10) numeric (0, -1, 10) 3`.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+
+use crate::papervm::{instructions::*, Condition, Instruction, Pos, Word};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(f64),
+    Word(i64, i64, usize),
+    Ord(Ordering),
+    Semi,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(name) => write!(f, "`{}`", name),
+            Token::Str(string) => write!(f, "{:?}", string),
+            Token::Num(num) => write!(f, "{}", num),
+            Token::Word(x, y, len) => write!(f, "({}, {}, {})", x, y, len),
+            Token::Ord(ord) => write!(f, "{:?}", ord),
+            Token::Semi => write!(f, "`;`"),
+        }
+    }
+}
+
+fn tokenize(line: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = line.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            '#' => break,
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            ';' => {
+                chars.next();
+                tokens.push(Token::Semi);
+            }
+            '<' | '=' | '>' => {
+                chars.next();
+                tokens.push(Token::Ord(match c {
+                    '<' => Ordering::Less,
+                    '=' => Ordering::Equal,
+                    _ => Ordering::Greater,
+                }));
+            }
+            '"' => {
+                chars.next();
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => string.push('\n'),
+                            Some('"') => string.push('"'),
+                            Some('\\') => string.push('\\'),
+                            other => return Err(format!("unknown escape `\\{:?}`", other)),
+                        },
+                        Some(c) => string.push(c),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+                tokens.push(Token::Str(string));
+            }
+            '(' => {
+                chars.next();
+                let inner: String = chars.by_ref().take_while(|&c| c != ')').collect();
+                let parts: Vec<&str> = inner.split(',').map(str::trim).collect();
+                let word = match parts[..] {
+                    [x, y, len] => x
+                        .parse()
+                        .ok()
+                        .zip(y.parse().ok())
+                        .zip(len.parse().ok())
+                        .map(|((x, y), len)| Token::Word(x, y, len)),
+                    _ => None,
+                };
+                tokens.push(word.ok_or(format!("invalid word `({})`", inner))?);
+            }
+            _ => {
+                let mut token = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "#;()\"".contains(c) {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+                if token.is_empty() {
+                    return Err(format!("unexpected `{}`", c));
+                }
+                match token.parse() {
+                    Ok(num) => tokens.push(Token::Num(num)),
+                    Err(_) => tokens.push(Token::Ident(token)),
+                }
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Line {
+    number: usize,
+    tokens: Vec<Token>,
+}
+
+struct Args<'a> {
+    tokens: std::slice::Iter<'a, Token>,
+}
+
+impl<'a> Args<'a> {
+    fn next(&mut self, expected: &str) -> Result<&'a Token, String> {
+        self.tokens
+            .next()
+            .ok_or(format!("expected {}, found end of line", expected))
+    }
+
+    fn unexpected(token: &Token, expected: &str) -> String {
+        format!("expected {}, found {}", expected, token)
+    }
+
+    fn word(&mut self) -> Result<Word, String> {
+        match self.next("word")? {
+            &Token::Word(x, y, len) => Ok(Word(Pos(x, y), len)),
+            t => Err(Self::unexpected(t, "word")),
+        }
+    }
+
+    fn int(&mut self) -> Result<i64, String> {
+        match self.next("integer")? {
+            &Token::Num(num) if num.fract() == 0. => Ok(num as i64),
+            t => Err(Self::unexpected(t, "integer")),
+        }
+    }
+
+    fn num(&mut self) -> Result<f64, String> {
+        match self.next("number")? {
+            &Token::Num(num) => Ok(num),
+            t => Err(Self::unexpected(t, "number")),
+        }
+    }
+
+    fn ordering(&mut self) -> Result<Ordering, String> {
+        match self.next("`<`, `=` or `>`")? {
+            &Token::Ord(ord) => Ok(ord),
+            t => Err(Self::unexpected(t, "`<`, `=` or `>`")),
+        }
+    }
+
+    fn string(&mut self) -> Result<&'a str, String> {
+        match self.next("string")? {
+            Token::Str(string) => Ok(string),
+            t => Err(Self::unexpected(t, "string")),
+        }
+    }
+
+    fn ident(&mut self) -> Result<&'a str, String> {
+        match self.next("name")? {
+            Token::Ident(name) => Ok(name),
+            t => Err(Self::unexpected(t, "name")),
+        }
+    }
+
+    /// A condition in prefix form, see [`Condition`].
+    fn condition(&mut self) -> Result<Condition, String> {
+        let name = self.ident()?;
+        Ok(match name {
+            "empty" => empty(self.word()?),
+            "contains" => {
+                let word = self.word()?;
+                let string = self.string()?;
+                let mut chars = string.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => contains(word, c),
+                    _ => return Err(format!("expected a single character, found {:?}", string)),
+                }
+            }
+            "numeric" => numeric(self.word()?),
+            "cmp_val" => cmp_val(self.word()?, self.ordering()?, self.num()?),
+            "cmp" => cmp(self.word()?, self.word()?, self.ordering()?),
+            "cmp_str" => cmp_str(self.word()?, self.word()?, self.ordering()?),
+            "equals_str" => equals_str(self.word()?, self.string()?),
+            "not" => self.condition()?.negate(),
+            "and" => self.condition()?.and(self.condition()?),
+            "or" => self.condition()?.or(self.condition()?),
+            _ => return Err(format!("unknown condition `{}`", name)),
+        })
+    }
+
+    /// Words up to the end of the line or the next `;`.
+    fn words(&mut self) -> Result<Vec<Word>, String> {
+        let mut words = vec![];
+        while let Some(token) = self.tokens.clone().next() {
+            if *token == Token::Semi {
+                break;
+            }
+            words.push(self.word()?);
+        }
+        Ok(words)
+    }
+
+    fn end(&mut self) -> Result<(), String> {
+        match self.tokens.next() {
+            Some(t) => Err(Self::unexpected(t, "end of line")),
+            None => Ok(()),
+        }
+    }
+}
+
+struct Assembler<'a> {
+    progs: HashMap<&'a str, &'a [Line]>,
+    compiled: HashMap<&'a str, Vec<Instruction>>,
+    /// Subprograms currently being assembled, to catch recursive calls
+    active: Vec<&'a str>,
+}
+
+impl<'a> Assembler<'a> {
+    fn prog(&mut self, name: &'a str) -> Result<Vec<Instruction>, String> {
+        if let Some(program) = self.compiled.get(name) {
+            return Ok(program.clone());
+        }
+        if self.active.contains(&name) {
+            return Err(format!("`{}` calls itself", name));
+        }
+        let lines = *self
+            .progs
+            .get(name)
+            .ok_or(format!("unknown program `{}`", name))?;
+
+        self.active.push(name);
+        let program = self
+            .assemble(lines)
+            .map_err(|e| format!("in `{}`: {}", name, e))?;
+        self.active.pop();
+
+        self.compiled.insert(name, program.clone());
+        Ok(program)
+    }
+
+    fn assemble(&mut self, lines: &'a [Line]) -> Result<Vec<Instruction>, ParseError> {
+        lines
+            .iter()
+            .map(|line| {
+                self.instruction(&line.tokens)
+                    .map_err(|message| ParseError {
+                        line: line.number,
+                        message,
+                    })
+            })
+            .collect()
+    }
+
+    fn instruction(&mut self, tokens: &'a [Token]) -> Result<Instruction, String> {
+        let mut args = Args {
+            tokens: tokens.iter(),
+        };
+        let name = args.ident()?;
+
+        let instruction = match name {
+            "write" => match args.next("string or number")? {
+                Token::Str(string) => write(string.as_str()),
+                &Token::Num(num) => write(num),
+                t => return Err(Args::unexpected(t, "string or number")),
+            },
+            "call" => {
+                let prog = self.prog(args.ident()?)?;
+                call(prog, args.words()?)
+            }
+            "fork" => {
+                let mut calls = vec![];
+                loop {
+                    let prog = self.prog(args.ident()?)?;
+                    calls.push((prog, args.words()?));
+                    if args.tokens.next().is_none() {
+                        break;
+                    }
+                }
+                fork(calls)
+            }
+            "join" => join(),
+            "circle" => circle(args.word()?),
+            "circle_all" => circle_all(args.words()?),
+            "add" => add(args.word()?, args.word()?),
+            "sub" => sub(args.word()?, args.word()?),
+            "modulo" => modulo(args.word()?, args.word()?),
+            "mul" => mul(args.word()?, args.word()?),
+            "div" => div(args.word()?, args.word()?),
+            "copy" => copy(args.word()?),
+            "copy_trimmed" => copy_trimmed(args.word()?),
+            "jump" => jump(args.int()?),
+            "jump_rel_if" => jump_rel_if(args.word()?, args.ordering()?, args.num()?, args.int()?),
+            "jump_rel_cmp" => {
+                jump_rel_cmp(args.word()?, args.word()?, args.ordering()?, args.int()?)
+            }
+            "jump_rel_if_str" => jump_rel_if_str(args.word()?, args.string()?, args.int()?),
+            "jump_rel_if_cond" => jump_rel_if_cond(args.condition()?, args.int()?),
+            "move_cursor" => move_cursor(args.int()?, args.int()?),
+            "stop" => stop(),
+            "breakpoint" => breakpoint(),
+            _ => return Err(format!("unknown instruction `{}`", name)),
+        };
+
+        args.end()?;
+        Ok(instruction)
+    }
+}
+
+/// Parses a program in the text format described in the [module docs](self).
+pub fn parse_program(source: &str) -> Result<Vec<Instruction>, ParseError> {
+    let mut lines = vec![];
+    for (i, line) in source.lines().enumerate() {
+        let tokens = tokenize(line).map_err(|message| ParseError {
+            line: i + 1,
+            message,
+        })?;
+        if !tokens.is_empty() {
+            lines.push(Line {
+                number: i + 1,
+                tokens,
+            });
+        }
+    }
+
+    let mut main = vec![];
+    let mut progs = HashMap::new();
+    let mut rest = &lines[..];
+    while let Some((line, tail)) = rest.split_first() {
+        match &line.tokens[..] {
+            [Token::Ident(keyword), Token::Ident(name)] if keyword == "prog" => {
+                let end = tail
+                    .iter()
+                    .position(|l| matches!(&l.tokens[..], [Token::Ident(k)] if k == "end"))
+                    .ok_or(ParseError {
+                        line: line.number,
+                        message: format!("program `{}` has no `end`", name),
+                    })?;
+                if progs.insert(name.as_str(), &tail[..end]).is_some() {
+                    return Err(ParseError {
+                        line: line.number,
+                        message: format!("program `{}` is defined twice", name),
+                    });
+                }
+                rest = &tail[end + 1..];
+            }
+            _ => {
+                main.push(line);
+                rest = tail;
+            }
+        }
+    }
+
+    let mut assembler = Assembler {
+        progs,
+        compiled: HashMap::new(),
+        active: vec![],
+    };
+
+    main.into_iter()
+        .map(|line| {
+            assembler
+                .instruction(&line.tokens)
+                .map_err(|message| ParseError {
+                    line: line.number,
+                    message,
+                })
+        })
+        .collect()
+}
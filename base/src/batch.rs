@@ -0,0 +1,257 @@
+use std::fmt::Debug;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::papervm::{Instruction, MemoryCell, PaperVM, VmError};
+
+#[derive(Debug, Clone)]
+pub struct BatchConfig {
+    pub threads: usize,
+    /// Runs taking more steps than this are aborted and reported as an error
+    pub max_steps: u64,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        BatchConfig {
+            threads: thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+            max_steps: 1_000_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum BatchError {
+    Vm(VmError),
+    StepLimit(u64),
+    Panic(String),
+}
+
+impl std::fmt::Display for BatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BatchError::Vm(e) => write!(f, "{}", e),
+            BatchError::StepLimit(steps) => write!(f, "step limit of {} reached", steps),
+            BatchError::Panic(msg) => write!(f, "panicked: {}", msg),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchRow {
+    pub input: String,
+    /// The circled words, with the `_` padding of numbers stripped
+    pub results: Vec<String>,
+    pub error: Option<BatchError>,
+    pub steps: u64,
+    pub sheets: usize,
+    pub cells: usize,
+    pub duration: Duration,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BatchTable {
+    pub rows: Vec<BatchRow>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BatchStats {
+    pub runs: usize,
+    pub errors: usize,
+    pub total_steps: u64,
+    pub max_steps: u64,
+    pub mean_steps: f64,
+    pub total_duration: Duration,
+}
+
+/// Runs the program built by `factory` for every input on a pool of worker
+/// threads. Rows are returned in the order of `inputs`.
+pub fn run_batch<T, I, F>(factory: F, inputs: &[I], config: &BatchConfig) -> BatchTable
+where
+    T: MemoryCell + Send,
+    I: Debug + Sync,
+    F: Fn(&I) -> Vec<Instruction> + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let rows = Mutex::new(Vec::with_capacity(inputs.len()));
+
+    thread::scope(|scope| {
+        for _ in 0..config.threads.max(1) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(input) = inputs.get(index) else {
+                    break;
+                };
+                let row = run_one::<T, I, F>(&factory, input, config.max_steps);
+                rows.lock().unwrap().push((index, row));
+            });
+        }
+    });
+
+    let mut rows = rows.into_inner().unwrap();
+    rows.sort_by_key(|(index, _)| *index);
+
+    BatchTable {
+        rows: rows.into_iter().map(|(_, row)| row).collect(),
+    }
+}
+
+fn run_one<T, I, F>(factory: &F, input: &I, max_steps: u64) -> BatchRow
+where
+    T: MemoryCell,
+    I: Debug,
+    F: Fn(&I) -> Vec<Instruction>,
+{
+    let start = Instant::now();
+    let mut steps = 0;
+
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut vm = PaperVM::<T>::new(factory(input));
+        loop {
+            if steps >= max_steps {
+                return (vm, Some(BatchError::StepLimit(max_steps)));
+            }
+            steps += 1;
+            match vm.try_step() {
+                Ok(result) if result.is_finished() => return (vm, None),
+                Ok(_) => {}
+                Err(e) => return (vm, Some(BatchError::Vm(e))),
+            }
+        }
+    }));
+
+    let mut row = BatchRow {
+        input: format!("{:?}", input),
+        results: vec![],
+        error: None,
+        steps,
+        sheets: 0,
+        cells: 0,
+        duration: Duration::ZERO,
+    };
+
+    match outcome {
+        Ok((vm, error)) => {
+            row.results = vm
+                .results::<Vec<Vec<char>>>()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|chars| chars.into_iter().collect::<String>().replace('_', " "))
+                .map(|result| result.trim().to_string())
+                .collect();
+            row.error = error;
+            row.sheets = count_sheets(&vm);
+            row.cells = vm.get_memory().len();
+        }
+        Err(panic) => {
+            let msg = panic
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| panic.downcast_ref::<&str>().map(|s| s.to_string()))
+                .unwrap_or_default();
+            row.error = Some(BatchError::Panic(msg));
+        }
+    }
+
+    row.duration = start.elapsed();
+    row
+}
+
+fn count_sheets<T: MemoryCell>(vm: &PaperVM<T>) -> usize {
+    1 + vm.finished_papers.iter().map(count_sheets).sum::<usize>()
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut result = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            c if c.is_control() => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+impl BatchTable {
+    pub fn stats(&self) -> BatchStats {
+        let runs = self.rows.len();
+        let total_steps = self.rows.iter().map(|row| row.steps).sum();
+        BatchStats {
+            runs,
+            errors: self.rows.iter().filter(|row| row.error.is_some()).count(),
+            total_steps,
+            max_steps: self.rows.iter().map(|row| row.steps).max().unwrap_or(0),
+            mean_steps: if runs == 0 {
+                0.
+            } else {
+                total_steps as f64 / runs as f64
+            },
+            total_duration: self.rows.iter().map(|row| row.duration).sum(),
+        }
+    }
+
+    /// Multiple results end up in one field, separated by spaces.
+    pub fn to_csv(&self) -> String {
+        let mut result = String::from("input,results,error,steps,sheets,cells,duration_us\n");
+        for row in &self.rows {
+            let fields = [
+                csv_field(&row.input),
+                csv_field(&row.results.join(" ")),
+                csv_field(
+                    &row.error
+                        .as_ref()
+                        .map(|e| e.to_string())
+                        .unwrap_or_default(),
+                ),
+                row.steps.to_string(),
+                row.sheets.to_string(),
+                row.cells.to_string(),
+                row.duration.as_micros().to_string(),
+            ];
+            result.push_str(&fields.join(","));
+            result.push('\n');
+        }
+        result
+    }
+
+    pub fn to_json(&self) -> String {
+        let rows: Vec<String> = self
+            .rows
+            .iter()
+            .map(|row| {
+                let results: Vec<String> = row.results.iter().map(|r| json_string(r)).collect();
+                format!(
+                    "{{\"input\":{},\"results\":[{}],\"error\":{},\"steps\":{},\"sheets\":{},\"cells\":{},\"duration_us\":{}}}",
+                    json_string(&row.input),
+                    results.join(","),
+                    row.error
+                        .as_ref()
+                        .map(|e| json_string(&e.to_string()))
+                        .unwrap_or("null".to_string()),
+                    row.steps,
+                    row.sheets,
+                    row.cells,
+                    row.duration.as_micros(),
+                )
+            })
+            .collect();
+        format!("[\n  {}\n]\n", rows.join(",\n  "))
+    }
+}
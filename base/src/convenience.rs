@@ -1,4 +1,4 @@
-use crate::papervm::{instructions::*, Instruction, IntoChars};
+use crate::papervm::{instructions::*, Instruction, IntoChars, Word};
 
 pub fn call_static<X: IntoChars>(
     program: Vec<Instruction>,
@@ -36,3 +36,27 @@ pub fn call_static<X: IntoChars>(
 
     main
 }
+
+/// Like [`call_static`], but circles every word the subroutine returns, in order.
+pub fn call_static_many<X: IntoChars>(
+    program: Vec<Instruction>,
+    inputs: Vec<X>,
+    return_sizes: Vec<usize>,
+) -> Vec<Instruction> {
+    let mut main = call_static(program, inputs, 0);
+    main.pop();
+
+    let total_length: usize = return_sizes.iter().sum();
+    let words: Vec<Word> = return_sizes
+        .iter()
+        .scan(-(total_length as i64), |x, &size| {
+            let word = Word::from((*x, 0, size));
+            *x += size as i64;
+            Some(word)
+        })
+        .collect();
+
+    main.push(circle_all(words));
+
+    main
+}
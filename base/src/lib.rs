@@ -1,4 +1,8 @@
+pub mod asm;
+pub mod batch;
 pub mod convenience;
+pub mod listing;
+pub mod optimize;
 pub mod papervm;
 pub mod papier;
 pub mod programs;
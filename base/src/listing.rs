@@ -0,0 +1,190 @@
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use crate::papervm::{Condition, Instruction, Word};
+
+#[derive(Debug, Clone, Default)]
+pub struct ListingOptions {
+    /// Name every jump target `L0`, `L1`, ... and jump to the names
+    pub labels: bool,
+}
+
+fn word(w: &Word) -> String {
+    format!("({}, {}, {})", w.0 .0, w.0 .1, w.1)
+}
+
+/// A string the way [`asm`](crate::asm) reads it, where only `\n`, `\"` and
+/// `\\` are escaped.
+fn quote(string: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in string.chars() {
+        match c {
+            '\n' => quoted.push_str("\\n"),
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn words(ws: &[Word]) -> String {
+    ws.iter().map(word).collect::<Vec<_>>().join(" ")
+}
+
+fn ordering(ord: &Ordering) -> &'static str {
+    match ord {
+        Ordering::Less => "<",
+        Ordering::Equal => "=",
+        Ordering::Greater => ">",
+    }
+}
+
+/// Conditions are written in prefix form, e.g. `and empty (0, 0, 10) not
+/// numeric (10, 0, 10)`.
+fn condition(c: &Condition) -> String {
+    match c {
+        Condition::Empty(w) => format!("empty {}", word(w)),
+        Condition::Contains(w, ch) => format!("contains {} {}", word(w), quote(&ch.to_string())),
+        Condition::Numeric(w) => format!("numeric {}", word(w)),
+        Condition::CmpVal(w, ord, val) => format!("cmp_val {} {} {}", word(w), ordering(ord), val),
+        Condition::Cmp(a, b, ord) => format!("cmp {} {} {}", word(a), word(b), ordering(ord)),
+        Condition::CmpStr(a, b, ord) => {
+            format!("cmp_str {} {} {}", word(a), word(b), ordering(ord))
+        }
+        Condition::EqualsStr(w, string) => format!("equals_str {} {}", word(w), quote(string)),
+        Condition::Not(c) => format!("not {}", condition(c)),
+        Condition::And(a, b) => format!("and {} {}", condition(a), condition(b)),
+        Condition::Or(a, b) => format!("or {} {}", condition(a), condition(b)),
+    }
+}
+
+/// The relative jump of an instruction, if it can jump at all.
+pub fn jump_offset(instruction: &Instruction) -> Option<i64> {
+    match instruction {
+        Instruction::Jump(rel)
+        | Instruction::JumpRelIf(_, _, _, rel)
+        | Instruction::JumpRelCmp(_, _, _, rel)
+        | Instruction::JumpRelIfStr(_, _, rel)
+        | Instruction::JumpRelIfCond(_, rel) => Some(*rel),
+        _ => None,
+    }
+}
+
+/// The same instruction with its relative jump replaced.
+pub fn with_jump(instruction: &Instruction, rel: i64) -> Instruction {
+    match instruction.clone() {
+        Instruction::Jump(_) => Instruction::Jump(rel),
+        Instruction::JumpRelIf(w, ord, val, _) => Instruction::JumpRelIf(w, ord, val, rel),
+        Instruction::JumpRelCmp(a, b, ord, _) => Instruction::JumpRelCmp(a, b, ord, rel),
+        Instruction::JumpRelIfStr(w, string, _) => Instruction::JumpRelIfStr(w, string, rel),
+        Instruction::JumpRelIfCond(condition, _) => Instruction::JumpRelIfCond(condition, rel),
+        other => other,
+    }
+}
+
+/// Formats a single instruction in the syntax of [`asm`](crate::asm). Called
+/// programs are only referred to by their length.
+pub fn mnemonic(instruction: &Instruction) -> String {
+    match instruction {
+        Instruction::Write(chars) => {
+            let string: String = chars.chars_ref().into_iter().collect();
+            format!("write {}", quote(&string))
+        }
+        Instruction::Call(program, args) => {
+            format!("call prog[{}] {}", program.len(), words(args))
+        }
+        Instruction::Fork(calls) => format!(
+            "fork {}",
+            calls
+                .iter()
+                .map(|(program, args)| format!("prog[{}] {}", program.len(), words(args)))
+                .collect::<Vec<_>>()
+                .join("; ")
+        ),
+        Instruction::Join => "join".to_string(),
+        Instruction::Circle(w) => format!("circle {}", word(w)),
+        Instruction::CircleAll(ws) => format!("circle_all {}", words(ws)),
+        Instruction::Add(a, b) => format!("add {} {}", word(a), word(b)),
+        Instruction::Sub(a, b) => format!("sub {} {}", word(a), word(b)),
+        Instruction::Mod(a, b) => format!("modulo {} {}", word(a), word(b)),
+        Instruction::Mul(a, b) => format!("mul {} {}", word(a), word(b)),
+        Instruction::Div(a, b) => format!("div {} {}", word(a), word(b)),
+        Instruction::Copy(w) => format!("copy {}", word(w)),
+        Instruction::TrimmedCopy(w) => format!("copy_trimmed {}", word(w)),
+        Instruction::Jump(rel) => format!("jump {}", rel),
+        Instruction::JumpRelCmp(a, b, ord, rel) => format!(
+            "jump_rel_cmp {} {} {} {}",
+            word(a),
+            word(b),
+            ordering(ord),
+            rel
+        ),
+        Instruction::JumpRelIf(w, ord, val, rel) => {
+            format!("jump_rel_if {} {} {} {}", word(w), ordering(ord), val, rel)
+        }
+        Instruction::JumpRelIfStr(w, string, rel) => {
+            format!("jump_rel_if_str {} {} {}", word(w), quote(string), rel)
+        }
+        Instruction::JumpRelIfCond(c, rel) => format!("jump_rel_if_cond {} {}", condition(c), rel),
+        Instruction::MoveCursor(pos) => format!("move_cursor {} {}", pos.0, pos.1),
+        Instruction::Stop => "stop".to_string(),
+        Instruction::BreakPoint => "breakpoint".to_string(),
+    }
+}
+
+fn list(program: &[Instruction], options: &ListingOptions, depth: usize, out: &mut String) {
+    let indent = "    ".repeat(depth);
+    let targets: BTreeSet<i64> = program
+        .iter()
+        .enumerate()
+        .filter_map(|(i, instruction)| jump_offset(instruction).map(|rel| i as i64 + rel))
+        .filter(|&target| target >= 0 && target < program.len() as i64)
+        .collect();
+    let label = |target: i64| {
+        targets
+            .iter()
+            .position(|&t| t == target)
+            .map(|n| format!("L{}", n))
+            .unwrap()
+    };
+
+    for (i, instruction) in program.iter().enumerate() {
+        if options.labels && targets.contains(&(i as i64)) {
+            writeln!(out, "{}{}:", indent, label(i as i64)).unwrap();
+        }
+
+        write!(out, "{}{:>4}  {}", indent, i, mnemonic(instruction)).unwrap();
+        if let Some(rel) = jump_offset(instruction) {
+            let target = i as i64 + rel;
+            if target < 0 || target >= program.len() as i64 {
+                write!(out, "  -> {} (outside program)", target).unwrap();
+            } else if options.labels {
+                write!(out, "  -> {}", label(target)).unwrap();
+            } else {
+                write!(out, "  -> {}", target).unwrap();
+            }
+        }
+        out.push('\n');
+
+        match instruction {
+            Instruction::Call(body, _) => list(body, options, depth + 1, out),
+            Instruction::Fork(calls) => {
+                for (body, _) in calls {
+                    list(body, options, depth + 1, out)
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Lists a program with instruction indices and resolved jump targets. The
+/// bodies of calls are listed in place, indented, with their own indices.
+pub fn listing(program: &[Instruction], options: &ListingOptions) -> String {
+    let mut out = String::new();
+    list(program, options, 0, &mut out);
+    out
+}
@@ -1,31 +1,310 @@
+use std::{error::Error, fmt, fs, process::ExitCode};
+
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
 use papier::{
-    convenience::call_static,
-    papervm::{instructions::circle, CharCell, PaperVM, StepResult, CHARS_PER_FLOAT},
-    programs::*,
+    asm::parse_program,
+    batch::{run_batch, BatchConfig},
+    listing::{listing, ListingOptions},
+    optimize::{optimize, verify, OptimizeOptions},
+    papervm::{instructions::write, CharCell, Instruction, MemoryCell, OverwritableCell, PaperVM},
 };
 
-fn main() {
-    let mut vm = PaperVM::<CharCell>::new(call_static(
-        vec![circle((0, 0, CHARS_PER_FLOAT))],
-        vec![98765432., 1234567.],
-        CHARS_PER_FLOAT,
-    ));
-    while vm.step().is_finished() {
-        println!("{}", vm.print());
+fn cli() -> Command {
+    Command::new("papier")
+        .about("Runs paper programs")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("run")
+                .about("Runs a program from a file and prints the final page")
+                .arg(Arg::new("program").required(true))
+                .arg(
+                    Arg::new("arg").long("arg").action(ArgAction::Append).help(
+                        "Written on the sheet before the program starts, like call arguments",
+                    ),
+                )
+                .arg(
+                    Arg::new("cell")
+                        .long("cell")
+                        .value_parser(["char", "overwritable"])
+                        .default_value("char"),
+                )
+                .arg(
+                    Arg::new("max-steps")
+                        .long("max-steps")
+                        .value_parser(value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("print-every")
+                        .long("print-every")
+                        .value_parser(value_parser!(u64))
+                        .help("Print the sheet being worked on every K steps"),
+                )
+                .arg(
+                    Arg::new("optimize")
+                        .long("optimize")
+                        .action(ArgAction::SetTrue)
+                        .help(
+                            "Run the peephole optimizer on the program first, if it writes \
+                             the same sheets",
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("batch")
+                .about("Runs a program once for every line of an inputs file, on several threads")
+                .arg(Arg::new("program").required(true))
+                .arg(
+                    Arg::new("inputs")
+                        .long("inputs")
+                        .required(true)
+                        .help("File with the arguments of a run on every line, split by spaces"),
+                )
+                .arg(
+                    Arg::new("cell")
+                        .long("cell")
+                        .value_parser(["char", "overwritable"])
+                        .default_value("char"),
+                )
+                .arg(
+                    Arg::new("threads")
+                        .long("threads")
+                        .value_parser(value_parser!(usize)),
+                )
+                .arg(
+                    Arg::new("max-steps")
+                        .long("max-steps")
+                        .value_parser(value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .value_parser(["csv", "json"])
+                        .default_value("csv"),
+                ),
+        )
+        .subcommand(
+            Command::new("list")
+                .about("Lists a program with resolved jump targets and called programs")
+                .arg(Arg::new("program").required(true))
+                .arg(
+                    Arg::new("labels")
+                        .long("labels")
+                        .action(ArgAction::SetTrue)
+                        .help("Name jump targets instead of using indices"),
+                )
+                .arg(
+                    Arg::new("optimize")
+                        .long("optimize")
+                        .action(ArgAction::SetTrue)
+                        .help("Run the peephole optimizer on the program first"),
+                ),
+        )
+}
+
+struct RunOptions {
+    args: Vec<String>,
+    optimize: bool,
+    max_steps: Option<u64>,
+    print_every: Option<u64>,
+}
+
+/// Steps to verify an optimized program in, without `--max-steps`
+const VERIFY_STEPS: u64 = 1_000_000;
+
+/// The optimized program if it writes the same sheets as the original with
+/// the arguments, otherwise the original.
+fn optimize_checked<T: MemoryCell>(
+    program: Vec<Instruction>,
+    args: &[String],
+    max_steps: u64,
+) -> Vec<Instruction> {
+    let optimized = optimize(program.clone(), &OptimizeOptions::default());
+    let args = || args.iter().map(String::as_str);
+    match verify::<T>(
+        with_args(&program, args()),
+        with_args(&optimized, args()),
+        max_steps,
+    ) {
+        Ok(()) => optimized,
+        Err(e) => {
+            eprintln!("warning: running the program unoptimized: {}", e);
+            program
+        }
+    }
+}
+
+/// Runs until the program circles its result. Errors are printed and turned
+/// into a failing exit code.
+fn run<T: MemoryCell>(program: &str, options: RunOptions) -> ExitCode {
+    let program = match parse_program(program) {
+        Ok(program) if options.optimize => optimize_checked::<T>(
+            program,
+            &options.args,
+            options.max_steps.unwrap_or(VERIFY_STEPS),
+        ),
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+
+    let mut vm = PaperVM::<T>::new(program);
+    for arg in &options.args {
+        match arg.parse::<f64>() {
+            Ok(num) => vm.write(&num),
+            Err(_) => vm.write(arg),
+        }
     }
-    println!("{}", vm.print());
-    dbg!(vm.result::<f64>().unwrap());
 
-    // let a = 127.;
-    // let b = 1322.;
+    let mut steps = 0;
+    let error = loop {
+        if options.max_steps.is_some_and(|max| steps >= max) {
+            break Some(format!("step limit of {} reached", steps));
+        }
+        steps += 1;
 
-    // dbg!(gcd_main(127., 1322.));
+        match vm.try_step() {
+            Ok(result) if result.is_finished() => break None,
+            Ok(_) => {}
+            Err(e) => break Some(e.to_string()),
+        }
+
+        if options.print_every.is_some_and(|k| k > 0 && steps % k == 0) {
+            println!("--- step {}\n{}", steps, vm.lowest_subroutine().print());
+        }
+    };
+
+    println!("---\n{}---", vm.print());
+    for result in vm.results::<Vec<Vec<char>>>().unwrap_or_default() {
+        let result: String = result.into_iter().collect();
+        println!("circled: {}", result.replace('_', " ").trim());
+    }
+    println!("steps: {}", steps);
+
+    match error {
+        Some(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+        None => ExitCode::SUCCESS,
+    }
+}
+
+/// A line of the inputs file, shown as it is in the table.
+struct BatchInput(String);
+
+impl fmt::Debug for BatchInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The program with its arguments written first, like `run --arg` does.
+fn with_args<'a>(
+    program: &[Instruction],
+    args: impl IntoIterator<Item = &'a str>,
+) -> Vec<Instruction> {
+    let mut instructions: Vec<Instruction> = args
+        .into_iter()
+        .map(|arg| match arg.parse::<f64>() {
+            Ok(num) => write(num),
+            Err(_) => write(arg.to_string()),
+        })
+        .collect();
+    instructions.extend_from_slice(program);
+    instructions
+}
+
+fn read_program(matches: &ArgMatches) -> Result<String, Box<dyn Error>> {
+    let path = matches.get_one::<String>("program").unwrap();
+    Ok(fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?)
+}
+
+fn batch_command(matches: &ArgMatches) -> Result<ExitCode, Box<dyn Error>> {
+    let program = parse_program(&read_program(matches)?)?;
+    let path = matches.get_one::<String>("inputs").unwrap();
+    let inputs: Vec<BatchInput> = fs::read_to_string(path)
+        .map_err(|e| format!("{}: {}", path, e))?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| BatchInput(line.trim().to_string()))
+        .collect();
+
+    let mut config = BatchConfig::default();
+    if let Some(&threads) = matches.get_one::<usize>("threads") {
+        config.threads = threads;
+    }
+    if let Some(&max_steps) = matches.get_one::<u64>("max-steps") {
+        config.max_steps = max_steps;
+    }
+
+    let factory = |input: &BatchInput| with_args(&program, input.0.split_whitespace());
+    let table = match matches.get_one::<String>("cell").unwrap().as_str() {
+        "overwritable" => run_batch::<OverwritableCell, _, _>(factory, &inputs, &config),
+        _ => run_batch::<CharCell, _, _>(factory, &inputs, &config),
+    };
+    match matches.get_one::<String>("format").unwrap().as_str() {
+        "json" => print!("{}", table.to_json()),
+        _ => print!("{}", table.to_csv()),
+    }
+
+    let stats = table.stats();
+    eprintln!(
+        "runs: {}, errors: {}, steps: {} (max {}, mean {:.1})",
+        stats.runs, stats.errors, stats.total_steps, stats.max_steps, stats.mean_steps
+    );
+    Ok(if stats.errors > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+fn list_command(matches: &ArgMatches) -> Result<ExitCode, Box<dyn Error>> {
+    let mut program = parse_program(&read_program(matches)?)?;
+    if matches.get_flag("optimize") {
+        program = optimize(program, &OptimizeOptions::default());
+    }
+    let options = ListingOptions {
+        labels: matches.get_flag("labels"),
+    };
+    print!("{}", listing(&program, &options));
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run_command(matches: &ArgMatches) -> Result<ExitCode, Box<dyn Error>> {
+    let program = read_program(matches)?;
+
+    let options = RunOptions {
+        args: matches
+            .get_many::<String>("arg")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        optimize: matches.get_flag("optimize"),
+        max_steps: matches.get_one::<u64>("max-steps").copied(),
+        print_every: matches.get_one::<u64>("print-every").copied(),
+    };
+
+    Ok(match matches.get_one::<String>("cell").unwrap().as_str() {
+        "overwritable" => run::<OverwritableCell>(&program, options),
+        _ => run::<CharCell>(&program, options),
+    })
+}
 
-    // let program = papier::programs::GCD;
+fn main() -> ExitCode {
+    let matches = cli().get_matches();
 
-    // let mut vm = PaperVM::<CharCell>::new(program);
-    // vm.run();
+    let result = match matches.subcommand() {
+        Some(("run", matches)) => run_command(matches),
+        Some(("batch", matches)) => batch_command(matches),
+        Some(("list", matches)) => list_command(matches),
+        _ => unreachable!(),
+    };
 
-    // let result: f64 = vm.result().unwrap();
-    // println!("GCD: {}", result);
+    result.unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        ExitCode::from(2)
+    })
 }
@@ -0,0 +1,204 @@
+use std::collections::BTreeSet;
+
+use crate::listing::{jump_offset, with_jump};
+use crate::papervm::{instructions::*, Instruction, MemoryCell, PaperVM, Pos};
+
+#[derive(Debug, Clone, Default)]
+pub struct OptimizeOptions {
+    /// Breakpoints only pause the TUI, removing them does not change the paper
+    pub remove_breakpoints: bool,
+}
+
+fn is_noop(instruction: &Instruction, options: &OptimizeOptions) -> bool {
+    match instruction {
+        Instruction::MoveCursor(Pos(0, 0)) => true,
+        Instruction::Write(chars) => chars.chars_ref().is_empty(),
+        Instruction::BreakPoint => options.remove_breakpoints,
+        // Jumping to the next instruction does the same as not jumping
+        _ => jump_offset(instruction) == Some(1),
+    }
+}
+
+/// Instructions that can be reached from the start of the program.
+fn reachable(program: &[Instruction]) -> Vec<bool> {
+    let mut reached = vec![false; program.len()];
+    let mut todo = vec![0i64];
+
+    while let Some(i) = todo.pop() {
+        if i < 0 || i >= program.len() as i64 || reached[i as usize] {
+            continue;
+        }
+        reached[i as usize] = true;
+
+        let instruction = &program[i as usize];
+        match instruction {
+            Instruction::Circle(_) | Instruction::CircleAll(_) | Instruction::Stop => {}
+            Instruction::Jump(rel) => todo.push(i + rel),
+            _ => {
+                todo.push(i + 1);
+                if let Some(rel) = jump_offset(instruction) {
+                    todo.push(i + rel);
+                }
+            }
+        }
+    }
+
+    reached
+}
+
+fn merge(a: &Instruction, b: &Instruction) -> Option<Instruction> {
+    match (a, b) {
+        (Instruction::Write(a), Instruction::Write(b)) => {
+            let mut chars = a.chars_ref();
+            chars.extend(b.chars_ref());
+            Some(write(chars))
+        }
+        (Instruction::MoveCursor(a), Instruction::MoveCursor(b)) => {
+            Some(move_cursor(a.0 + b.0, a.1 + b.1))
+        }
+        _ => None,
+    }
+}
+
+/// One round of rewrites, returns `None` when nothing changed.
+fn pass(program: &[Instruction], options: &OptimizeOptions) -> Option<Vec<Instruction>> {
+    let reached = reachable(program);
+    let targets: BTreeSet<i64> = program
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| reached[*i])
+        .filter_map(|(i, instruction)| jump_offset(instruction).map(|rel| i as i64 + rel))
+        .collect();
+
+    // Instructions that stay, with the range of old indices merged into them
+    let mut kept: Vec<(usize, usize, Instruction)> = vec![];
+    for (i, instruction) in program.iter().enumerate() {
+        if !reached[i] || is_noop(instruction, options) {
+            continue;
+        }
+        if let Some((_, end, prev)) = kept.last_mut() {
+            // Only merge when nothing can jump in between the two
+            let adjacent = (*end + 1..=i).all(|j| !targets.contains(&(j as i64)));
+            if let Some(merged) = merge(prev, instruction).filter(|_| adjacent) {
+                *prev = merged;
+                *end = i;
+                continue;
+            }
+        }
+        kept.push((i, i, instruction.clone()));
+    }
+
+    if kept.len() == program.len() {
+        return None;
+    }
+
+    // Removed instructions map to the next kept one
+    let old_len = program.len() as i64;
+    let new_len = kept.len() as i64;
+    let map = |target: i64| {
+        if target < 0 {
+            target
+        } else if target >= old_len {
+            new_len + target - old_len
+        } else {
+            kept.iter()
+                .filter(|(_, end, _)| (*end as i64) < target)
+                .count() as i64
+        }
+    };
+
+    Some(
+        kept.iter()
+            .enumerate()
+            .map(
+                |(k, (start, _, instruction))| match jump_offset(instruction) {
+                    Some(rel) => with_jump(instruction, map(*start as i64 + rel) - k as i64),
+                    None => instruction.clone(),
+                },
+            )
+            .collect(),
+    )
+}
+
+/// Merges consecutive `MoveCursor`s and `Write`s and removes unreachable
+/// instructions and jumps to the next instruction. Relative jumps are
+/// rewritten to keep pointing at the same instruction, called and forked
+/// programs are optimized as well. The sheets written stay exactly the same.
+pub fn optimize(program: Vec<Instruction>, options: &OptimizeOptions) -> Vec<Instruction> {
+    let mut program: Vec<Instruction> = program
+        .into_iter()
+        .map(|instruction| match instruction {
+            Instruction::Call(body, args) => Instruction::Call(optimize(body, options), args),
+            Instruction::Fork(calls) => Instruction::Fork(
+                calls
+                    .into_iter()
+                    .map(|(body, args)| (optimize(body, options), args))
+                    .collect(),
+            ),
+            other => other,
+        })
+        .collect();
+
+    while let Some(optimized) = pass(&program, options) {
+        program = optimized;
+    }
+
+    program
+}
+
+fn collect_sheets<T: MemoryCell>(vm: &PaperVM<T>, sheets: &mut Vec<String>) {
+    sheets.push(vm.print());
+    for paper in &vm.finished_papers {
+        collect_sheets(paper, sheets);
+    }
+}
+
+fn run_sheets<T: MemoryCell>(
+    program: Vec<Instruction>,
+    max_steps: u64,
+) -> Result<Vec<String>, String> {
+    let mut vm = PaperVM::<T>::new(program);
+    for _ in 0..max_steps {
+        if vm.try_step().map_err(|e| e.to_string())?.is_finished() {
+            let mut sheets = vec![];
+            collect_sheets(&vm, &mut sheets);
+            return Ok(sheets);
+        }
+    }
+    Err(format!("step limit of {} reached", max_steps))
+}
+
+/// Runs both programs and checks that every sheet ends up the same.
+pub fn verify<T: MemoryCell>(
+    original: Vec<Instruction>,
+    optimized: Vec<Instruction>,
+    max_steps: u64,
+) -> Result<(), String> {
+    let original = run_sheets::<T>(original, max_steps)?;
+    let optimized = run_sheets::<T>(optimized, max_steps)?;
+
+    if original.len() != optimized.len() {
+        return Err(format!(
+            "{} sheets were written instead of {}",
+            optimized.len(),
+            original.len()
+        ));
+    }
+    for (i, (a, b)) in original.iter().zip(optimized.iter()).enumerate() {
+        if a != b {
+            return Err(format!("sheet {} differs:\n{}\ninstead of\n{}", i, b, a));
+        }
+    }
+    Ok(())
+}
+
+/// [`optimize`], but only returns the result if [`verify`] agrees.
+pub fn optimize_verified<T: MemoryCell>(
+    program: Vec<Instruction>,
+    options: &OptimizeOptions,
+    max_steps: u64,
+) -> Result<Vec<Instruction>, String> {
+    let optimized = optimize(program.clone(), options);
+    verify::<T>(program, optimized.clone(), max_steps)?;
+    Ok(optimized)
+}
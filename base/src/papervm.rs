@@ -43,11 +43,21 @@ impl MemoryCell for OverwritableCell {
         let mut rng = rand::thread_rng();
         self.values.choose(&mut rng).copied().unwrap_or(' ')
     }
+
+    fn history(&self) -> Vec<char> {
+        self.values.clone()
+    }
 }
 
 pub trait MemoryCell: Default + Debug + Clone {
     fn write(&mut self, value: char);
     fn read(&self) -> char;
+
+    /// Everything written to the cell, oldest first. Cells that forget what
+    /// was written over them only have the value they hold.
+    fn history(&self) -> Vec<char> {
+        vec![self.read()]
+    }
 }
 
 pub trait FromChars: Debug + Send + Sync {
@@ -78,6 +88,38 @@ impl FromChars for Vec<char> {
     }
 }
 
+/// Conversion from every circled word of a finished sheet.
+pub trait FromResults: Sized {
+    fn from_results(results: Vec<Vec<char>>) -> Option<Self>;
+}
+
+impl<T: FromChars> FromResults for Vec<T> {
+    fn from_results(results: Vec<Vec<char>>) -> Option<Vec<T>> {
+        Some(results.into_iter().map(T::from_chars).collect())
+    }
+}
+
+impl<A: FromChars, B: FromChars> FromResults for (A, B) {
+    fn from_results(results: Vec<Vec<char>>) -> Option<(A, B)> {
+        let mut results = results.into_iter();
+        Some((
+            A::from_chars(results.next()?),
+            B::from_chars(results.next()?),
+        ))
+    }
+}
+
+impl<A: FromChars, B: FromChars, C: FromChars> FromResults for (A, B, C) {
+    fn from_results(results: Vec<Vec<char>>) -> Option<(A, B, C)> {
+        let mut results = results.into_iter();
+        Some((
+            A::from_chars(results.next()?),
+            B::from_chars(results.next()?),
+            C::from_chars(results.next()?),
+        ))
+    }
+}
+
 pub trait IntoChars: Debug + Send + Sync {
     fn chars_ref(&self) -> Vec<char>;
 }
@@ -101,9 +143,20 @@ impl IntoChars for char {
 }
 
 impl IntoChars for f64 {
+    /// Fractions are rounded to fit in `CHARS_PER_FLOAT` characters, e.g.
+    /// after a division.
     fn chars_ref(&self) -> Vec<char> {
-        format!("{:width$}", self, width = CHARS_PER_FLOAT)
-            .chars()
+        let mut text = format!("{:width$}", self, width = CHARS_PER_FLOAT);
+        if text.len() > CHARS_PER_FLOAT && self.is_finite() {
+            let whole = format!("{:.0}", self).len();
+            let decimals = CHARS_PER_FLOAT.saturating_sub(whole + 1);
+            text = format!("{:width$.decimals$}", self, width = CHARS_PER_FLOAT);
+            if text.contains('.') {
+                text = text.trim_end_matches('0').trim_end_matches('.').to_string();
+                text = format!("{:>width$}", text, width = CHARS_PER_FLOAT);
+            }
+        }
+        text.chars()
             .map(|c| match c {
                 ' ' => '_',
                 _ => c,
@@ -140,16 +193,26 @@ impl IntoChars for Vec<char> {
 pub enum Instruction {
     Write(Arc<dyn IntoChars>),
     Call(Vec<Instruction>, Vec<Word>),
+    /// Starts several sheets that are worked on alongside this one, round-robin
+    Fork(Vec<(Vec<Instruction>, Vec<Word>)>),
+    /// Waits for all forked sheets and copies their circled words back in order
+    Join,
     Circle(Word),
+    /// Circles several words, which are all copied back to the parent in order
+    CircleAll(Vec<Word>),
     Add(Word, Word),
     Sub(Word, Word),
     Mod(Word, Word),
+    Mul(Word, Word),
+    Div(Word, Word),
     Copy(Word),
     TrimmedCopy(Word),
     Jump(i64),
     JumpRelCmp(Word, Word, Ordering, i64),
     JumpRelIf(Word, Ordering, f64, i64),
     JumpRelIfStr(Word, String, i64),
+    /// Jumps relatively when the condition holds
+    JumpRelIfCond(Condition, i64),
     /// Moves the cursor relatively by the given position
     MoveCursor(Pos),
     Stop,
@@ -173,10 +236,27 @@ impl std::fmt::Display for Instruction {
             Instruction::Call(instructions, args) => {
                 write!(f, "Call prog[{}]({:?})", instructions.len(), args)
             }
+            Instruction::Fork(calls) => {
+                write!(f, "Fork")?;
+                for (instructions, args) in calls {
+                    write!(f, " prog[{}]({:?})", instructions.len(), args)?;
+                }
+                Ok(())
+            }
+            Instruction::Join => write!(f, "Join"),
             Instruction::Circle(w) => write!(f, "Circle {}", w),
+            Instruction::CircleAll(words) => {
+                write!(f, "CircleAll")?;
+                for w in words {
+                    write!(f, " {}", w)?;
+                }
+                Ok(())
+            }
             Instruction::Add(w1, w2) => write!(f, "Add {} {}", w1, w2),
             Instruction::Sub(w1, w2) => write!(f, "Sub {} {}", w1, w2),
             Instruction::Mod(w1, w2) => write!(f, "Mod {} {}", w1, w2),
+            Instruction::Mul(w1, w2) => write!(f, "Mul {} {}", w1, w2),
+            Instruction::Div(w1, w2) => write!(f, "Div {} {}", w1, w2),
             Instruction::Copy(w) => write!(f, "Copy {}", w),
             Instruction::TrimmedCopy(w) => write!(f, "TrimmedCopy {}", w),
             Instruction::Jump(val) => write!(f, "Jump {}", val),
@@ -192,10 +272,88 @@ impl std::fmt::Display for Instruction {
             Instruction::JumpRelIfStr(word, string, jump) => {
                 write!(f, "JumpRelIfStr {word} {string} {jump}")
             }
+            Instruction::JumpRelIfCond(condition, jump) => {
+                write!(f, "JumpRelIfCond {condition} {jump}")
+            }
         }
     }
 }
 
+#[derive(Debug, Clone)]
+pub enum Condition {
+    /// The word only contains whitespace or unwritten cells
+    Empty(Word),
+    Contains(Word, char),
+    /// The word parses as a number
+    Numeric(Word),
+    /// Numeric comparison of a word with a value
+    CmpVal(Word, Ordering, f64),
+    /// Numeric comparison of two words
+    Cmp(Word, Word, Ordering),
+    /// Compares the text of two words, ignoring surrounding whitespace
+    CmpStr(Word, Word, Ordering),
+    /// The text of the word, ignoring surrounding whitespace, equals the string
+    EqualsStr(Word, String),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+impl Condition {
+    pub fn and(self, other: Condition) -> Condition {
+        Condition::And(Box::new(self), Box::new(other))
+    }
+
+    pub fn or(self, other: Condition) -> Condition {
+        Condition::Or(Box::new(self), Box::new(other))
+    }
+
+    pub fn negate(self) -> Condition {
+        Condition::Not(Box::new(self))
+    }
+
+    /// Every word the condition reads.
+    pub fn words(&self) -> Vec<Word> {
+        match self {
+            Condition::Empty(w)
+            | Condition::Contains(w, _)
+            | Condition::Numeric(w)
+            | Condition::CmpVal(w, _, _)
+            | Condition::EqualsStr(w, _) => vec![*w],
+            Condition::Cmp(a, b, _) | Condition::CmpStr(a, b, _) => vec![*a, *b],
+            Condition::Not(c) => c.words(),
+            Condition::And(a, b) | Condition::Or(a, b) => {
+                let mut words = a.words();
+                words.extend(b.words());
+                words
+            }
+        }
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Condition::Empty(w) => write!(f, "empty {}", w),
+            Condition::Contains(w, c) => write!(f, "{} contains {:?}", w, c),
+            Condition::Numeric(w) => write!(f, "numeric {}", w),
+            Condition::CmpVal(w, ord, val) => write!(f, "{} {:?} {}", w, ord, val),
+            Condition::Cmp(a, b, ord) => write!(f, "{} {:?} {}", a, ord, b),
+            Condition::CmpStr(a, b, ord) => write!(f, "str {} {:?} {}", a, ord, b),
+            Condition::EqualsStr(w, string) => write!(f, "{} == {:?}", w, string),
+            Condition::Not(c) => write!(f, "not ({})", c),
+            Condition::And(a, b) => write!(f, "({}) and ({})", a, b),
+            Condition::Or(a, b) => write!(f, "({}) or ({})", a, b),
+        }
+    }
+}
+
+pub fn compare_f64(a: f64, b: f64, ordering: Ordering) -> bool {
+    a.partial_cmp(&b) == Some(ordering)
+        // special case for floating point equality
+        || (ordering == Ordering::Equal && (a - b).abs() < f32::EPSILON as f64)
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Pos(pub i64, pub i64);
 
@@ -237,6 +395,9 @@ where
 pub struct SimStepState {
     pub instruction: Instruction,
     pub cursor: Pos,
+    /// Indices of the calls the instruction is in, followed by its own
+    /// index. Steps on forked sheets only have the index on their sheet.
+    pub trace: Vec<usize>,
 }
 
 #[derive(Debug)]
@@ -251,27 +412,106 @@ impl StepResult {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum VmError {
+    /// A `Stop` instruction was executed
+    Stopped,
+    /// The instruction counter points outside of the program
+    OutOfProgram(i64),
+    /// The sheet circled its result while this many forked sheets were not
+    /// joined yet
+    UnjoinedForks(usize),
+}
+
+impl Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VmError::Stopped => write!(f, "STOP"),
+            VmError::OutOfProgram(counter) => {
+                write!(f, "instruction counter {} is outside the program", counter)
+            }
+            VmError::UnjoinedForks(forks) => {
+                write!(f, "circled with {} forked sheets not joined", forks)
+            }
+        }
+    }
+}
+
+impl std::error::Error for VmError {}
+
+/// Where a sheet was called from on the sheet that called it.
+#[derive(Debug, Clone)]
+pub struct CallSite {
+    /// Cursor of the calling sheet at the `Call` or `Fork`
+    pub cursor: Pos,
+    /// Where the circled words were copied to on the calling sheet, once the
+    /// sheet is finished
+    pub results: Vec<Word>,
+}
+
+/// The kind of instruction that wrote a cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WriteKind {
+    Write,
+    /// `Copy` or `TrimmedCopy`
+    Copy,
+    /// `Add`, `Sub`, `Mod`, `Mul` or `Div`
+    Arithmetic,
+    /// A circled word of a finished `Call` or `Fork`
+    Result,
+    /// Written before the program started, like the arguments of a call
+    Argument,
+}
+
+/// What last wrote a cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Writer {
+    pub kind: WriteKind,
+    /// Index of the instruction on the sheet. For results it is the `Call`
+    /// or `Join`, arguments have none.
+    pub index: Option<usize>,
+}
+
+impl Writer {
+    const ARGUMENT: Writer = Writer {
+        kind: WriteKind::Argument,
+        index: None,
+    };
+}
+
 #[derive(Clone)]
 pub struct PaperVM<T: MemoryCell> {
     memory: HashMap<Pos, T>,
+    writers: HashMap<Pos, Writer>,
     cursor: Pos,
     program: Vec<Instruction>,
-    circled: Option<Word>,
+    circled: Vec<Word>,
+    /// Set by `Circle` and `CircleAll`, which may circle nothing
+    finished: bool,
     instruction_counter: i64,
     pub subroutine: Option<Box<PaperVM<T>>>,
+    pub forks: Vec<PaperVM<T>>,
+    fork_turn: usize,
     pub finished_papers: Vec<PaperVM<T>>,
+    /// `None` for the sheet a run starts with
+    pub call_site: Option<CallSite>,
 }
 
 impl<T: MemoryCell> PaperVM<T> {
     pub fn new(program: Vec<Instruction>) -> PaperVM<T> {
         PaperVM {
             memory: HashMap::new(),
+            writers: HashMap::new(),
             cursor: Pos(0, 0),
             program,
-            circled: None,
+            circled: vec![],
+            finished: false,
             instruction_counter: 0,
             subroutine: None,
+            forks: vec![],
+            fork_turn: 0,
             finished_papers: vec![],
+            call_site: None,
         }
     }
 
@@ -279,6 +519,11 @@ impl<T: MemoryCell> PaperVM<T> {
         &self.memory
     }
 
+    /// What last wrote each cell of the sheet.
+    pub fn get_writers(&self) -> &HashMap<Pos, Writer> {
+        &self.writers
+    }
+
     pub fn lowest_subroutine(&self) -> &PaperVM<T> {
         if let Some(vm) = &self.subroutine {
             vm.lowest_subroutine()
@@ -287,12 +532,38 @@ impl<T: MemoryCell> PaperVM<T> {
         }
     }
 
+    /// All sheets that are currently being worked on: the forked siblings
+    /// of the deepest call, or just the deepest sheet.
+    pub fn lowest_subroutines(&self) -> Vec<&PaperVM<T>> {
+        if let Some(vm) = &self.subroutine {
+            vm.lowest_subroutines()
+        } else if !self.forks.is_empty() {
+            self.forks
+                .iter()
+                .flat_map(|vm| vm.lowest_subroutines())
+                .collect()
+        } else {
+            vec![self]
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
     pub fn get_circled(&self) -> Option<Word> {
-        self.circled.map(|mut x| {
-            x.0 .0 += self.cursor.0;
-            x.0 .1 += self.cursor.1;
-            x
-        })
+        self.get_circled_all().first().copied()
+    }
+
+    pub fn get_circled_all(&self) -> Vec<Word> {
+        self.circled
+            .iter()
+            .map(|&(mut x)| {
+                x.0 .0 += self.cursor.0;
+                x.0 .1 += self.cursor.1;
+                x
+            })
+            .collect()
     }
 
     pub fn cursor(&self) -> Pos {
@@ -304,7 +575,15 @@ impl<T: MemoryCell> PaperVM<T> {
         let b: f64 = self.read(b);
 
         let result = op(a, b);
-        self.write(&result);
+        self.write_as(&result, self.writer(WriteKind::Arithmetic));
+    }
+
+    /// The instruction being run as the writer of what it writes.
+    fn writer(&self, kind: WriteKind) -> Writer {
+        Writer {
+            kind,
+            index: Some(self.instruction_counter as usize),
+        }
     }
 
     pub fn aread(&self, x: i64, y: i64) -> String {
@@ -337,77 +616,165 @@ impl<T: MemoryCell> PaperVM<T> {
         result
     }
 
+    /// Copies the circled words of the finished sheet, which was started by
+    /// the instruction at `index`.
+    fn write_results(&mut self, vm: &mut PaperVM<T>, index: usize) {
+        let writer = Writer {
+            kind: WriteKind::Result,
+            index: Some(index),
+        };
+        let mut results = vec![];
+        for &word in vm.circled.iter() {
+            let chars = vm.read::<Vec<char>>(word);
+            results.push(Word(self.cursor, chars.len()));
+            self.write_as(&chars, writer);
+        }
+        if let Some(call_site) = &mut vm.call_site {
+            call_site.results = results;
+        }
+    }
+
+    /// Gives the next forked sheet its turn. Returns `None` when it is this
+    /// sheet's turn instead, which is skipped while waiting on a `Join`.
+    fn step_forks(&mut self) -> Result<Option<StepResult>, VmError> {
+        let waiting = matches!(
+            self.program.get(self.instruction_counter as usize),
+            Some(Instruction::Join)
+        ) && !self.forks.iter().all(|vm| vm.is_finished());
+        let turns = self.forks.len() + 1;
+
+        for _ in 0..turns {
+            self.fork_turn = (self.fork_turn + 1) % turns;
+            if self.fork_turn == 0 {
+                if waiting {
+                    continue;
+                }
+                return Ok(None);
+            }
+
+            let vm = &mut self.forks[self.fork_turn - 1];
+            if vm.is_finished() {
+                continue;
+            }
+            if let StepResult::Running(state) = vm.try_step()? {
+                return Ok(Some(StepResult::Running(state)));
+            }
+        }
+
+        Ok(None)
+    }
+
     pub fn step(&mut self) -> StepResult {
-        if let Some(result) = self.subroutine.as_mut().map(|x| x.step()) {
-            if result.is_finished() {
-                let word = self.subroutine.as_ref().unwrap().circled.unwrap();
-                self.write(&self.subroutine.as_ref().unwrap().read::<Vec<char>>(word));
-                self.finished_papers.push(*self.subroutine.take().unwrap());
-            } else {
-                return result;
+        self.try_step().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_step(&mut self) -> Result<StepResult, VmError> {
+        if let Some(result) = self.subroutine.as_mut().map(|x| x.try_step()) {
+            match result? {
+                StepResult::Finished => {
+                    let mut subroutine = self.subroutine.take().unwrap();
+                    // The counter is already past the call
+                    let call = self.instruction_counter as usize - 1;
+                    self.write_results(&mut subroutine, call);
+                    self.finished_papers.push(*subroutine);
+                }
+                StepResult::Running(mut state) => {
+                    // The counter is already past the call
+                    state.trace.insert(0, self.instruction_counter as usize - 1);
+                    return Ok(StepResult::Running(state));
+                }
             }
         }
 
-        let instruction = self.program[self.instruction_counter as usize].clone();
+        if !self.forks.is_empty() {
+            if let Some(result) = self.step_forks()? {
+                return Ok(result);
+            }
+        }
+
+        let instruction = self
+            .program
+            .get(self.instruction_counter as usize)
+            .ok_or(VmError::OutOfProgram(self.instruction_counter))?
+            .clone();
 
         let sim_step_state = SimStepState {
             instruction: instruction.clone(),
             cursor: self.cursor,
+            trace: vec![self.instruction_counter as usize],
         };
 
         match instruction.clone() {
-            Instruction::Write(chars) => self.write(&chars),
+            Instruction::Write(chars) => self.write_as(&chars, self.writer(WriteKind::Write)),
             Instruction::Call(instructions, args) => {
                 let mut vm: PaperVM<T> = PaperVM::new(instructions);
+                vm.call_site = Some(CallSite {
+                    cursor: self.cursor,
+                    results: vec![],
+                });
                 for arg in args {
                     vm.write(&self.read::<Vec<char>>(arg));
                 }
                 self.subroutine = Some(Box::new(vm));
                 self.instruction_counter += 1;
-                return StepResult::Running(sim_step_state);
+                return Ok(StepResult::Running(sim_step_state));
+            }
+            Instruction::Fork(calls) => {
+                for (instructions, args) in calls {
+                    let mut vm: PaperVM<T> = PaperVM::new(instructions);
+                    vm.call_site = Some(CallSite {
+                        cursor: self.cursor,
+                        results: vec![],
+                    });
+                    for arg in args {
+                        vm.write(&self.read::<Vec<char>>(arg));
+                    }
+                    self.forks.push(vm);
+                }
             }
-            Instruction::Circle(arg) => {
-                self.circled = Some(arg);
-                return StepResult::Finished;
+            Instruction::Join => {
+                for mut vm in std::mem::take(&mut self.forks) {
+                    self.write_results(&mut vm, self.instruction_counter as usize);
+                    self.finished_papers.push(vm);
+                }
             }
+            Instruction::Circle(arg) => return self.finish(vec![arg]),
+            Instruction::CircleAll(args) => return self.finish(args),
             Instruction::Add(a, b) => self.op(a, b, |a, b| a + b),
             Instruction::Sub(a, b) => self.op(a, b, |a, b| a - b),
             Instruction::Mod(a, b) => self.op(a, b, |a, b| a % b),
+            Instruction::Mul(a, b) => self.op(a, b, |a, b| a * b),
+            Instruction::Div(a, b) => self.op(a, b, |a, b| a / b),
 
-            Instruction::Copy(a) => self.write(&self.read::<Vec<char>>(a)),
+            Instruction::Copy(a) => {
+                let a: Vec<char> = self.read(a);
+                self.write_as(&a, self.writer(WriteKind::Copy));
+            }
             Instruction::TrimmedCopy(a) => {
                 let mut a: Vec<char> = self.read(a);
                 a.retain(|x| !x.is_whitespace());
-                self.write(&a);
+                self.write_as(&a, self.writer(WriteKind::Copy));
             }
             Instruction::Jump(rel_jump) => {
                 self.instruction_counter += rel_jump;
-                return StepResult::Running(sim_step_state);
+                return Ok(StepResult::Running(sim_step_state));
             }
             Instruction::JumpRelIf(a, ordering, val, rel_jump) => {
                 let a: f64 = self.read(a);
-                let cmp = a.partial_cmp(&val);
-                if cmp == Some(ordering)
-                        // special case for floating point equality
-                        || (ordering == Ordering::Equal && (a - val).abs() < f32::EPSILON as f64)
-                {
+                if compare_f64(a, val, ordering) {
                     self.instruction_counter += rel_jump;
-                    return StepResult::Running(sim_step_state);
+                    return Ok(StepResult::Running(sim_step_state));
                 }
             }
             Instruction::JumpRelCmp(w1, w2, ordering, rel_jump) => {
                 let a: f64 = self.read(w1);
                 let b: f64 = self.read(w2);
-                let cmp = a.partial_cmp(&b);
-                if cmp == Some(ordering)
-                        // special case for floating point equality
-                        || (ordering == Ordering::Equal && (a - b).abs() < f32::EPSILON as f64)
-                {
+                if compare_f64(a, b, ordering) {
                     self.instruction_counter += rel_jump;
-                    return StepResult::Running(sim_step_state);
+                    return Ok(StepResult::Running(sim_step_state));
                 }
             }
-            Instruction::Stop => panic!("STOP"),
+            Instruction::Stop => return Err(VmError::Stopped),
             // For visual sims only
             Instruction::BreakPoint => {}
             Instruction::MoveCursor(pos) => {
@@ -417,13 +784,56 @@ impl<T: MemoryCell> PaperVM<T> {
                 let a: Vec<char> = self.read(word);
                 if a.into_iter().collect::<String>() == string {
                     self.instruction_counter += jump;
-                    return StepResult::Running(sim_step_state);
+                    return Ok(StepResult::Running(sim_step_state));
+                }
+            }
+            Instruction::JumpRelIfCond(condition, jump) => {
+                if self.holds(&condition) {
+                    self.instruction_counter += jump;
+                    return Ok(StepResult::Running(sim_step_state));
                 }
             }
         }
         self.instruction_counter += 1;
 
-        StepResult::Running(sim_step_state)
+        Ok(StepResult::Running(sim_step_state))
+    }
+
+    /// Circles the words, which ends the sheet. Forked sheets have to be
+    /// joined first, or their results would be lost.
+    fn finish(&mut self, words: Vec<Word>) -> Result<StepResult, VmError> {
+        if !self.forks.is_empty() {
+            return Err(VmError::UnjoinedForks(self.forks.len()));
+        }
+        self.circled = words;
+        self.finished = true;
+        Ok(StepResult::Finished)
+    }
+
+    fn read_text(&self, word: Word) -> String {
+        self.read::<Vec<char>>(word).into_iter().collect()
+    }
+
+    pub fn holds(&self, condition: &Condition) -> bool {
+        match condition {
+            Condition::Empty(w) => self.read_text(*w).trim().is_empty(),
+            Condition::Contains(w, c) => self.read_text(*w).contains(*c),
+            Condition::Numeric(w) => self
+                .read_text(*w)
+                .replace('_', " ")
+                .trim()
+                .parse::<f64>()
+                .is_ok(),
+            Condition::CmpVal(w, ordering, val) => compare_f64(self.read(*w), *val, *ordering),
+            Condition::Cmp(a, b, ordering) => compare_f64(self.read(*a), self.read(*b), *ordering),
+            Condition::CmpStr(a, b, ordering) => {
+                self.read_text(*a).trim().cmp(self.read_text(*b).trim()) == *ordering
+            }
+            Condition::EqualsStr(w, string) => self.read_text(*w).trim() == string.trim(),
+            Condition::Not(c) => !self.holds(c),
+            Condition::And(a, b) => self.holds(a) && self.holds(b),
+            Condition::Or(a, b) => self.holds(a) || self.holds(b),
+        }
     }
 
     pub fn run(&mut self) {
@@ -452,7 +862,12 @@ impl<T: MemoryCell> PaperVM<T> {
         O::from_chars(chars)
     }
 
+    /// Writes at the cursor as an argument, before the program is run.
     pub fn write(&mut self, value: &impl IntoChars) {
+        self.write_as(value, Writer::ARGUMENT);
+    }
+
+    fn write_as(&mut self, value: &impl IntoChars, writer: Writer) {
         let chars = value.chars_ref();
         for c in chars.iter() {
             if *c == '\n' {
@@ -464,6 +879,7 @@ impl<T: MemoryCell> PaperVM<T> {
                 continue;
             }
             let cell = self.memory.entry(self.cursor).or_default();
+            self.writers.insert(self.cursor, writer);
 
             self.cursor = self.cursor.next();
             cell.write(*c);
@@ -472,7 +888,18 @@ impl<T: MemoryCell> PaperVM<T> {
 
     pub fn result<O: FromChars>(&mut self) -> Option<O> {
         self.circled
-            .map(|word| O::from_chars(self.read::<Vec<char>>(word)))
+            .first()
+            .map(|&word| O::from_chars(self.read::<Vec<char>>(word)))
+    }
+
+    /// Reads all circled words at once, e.g. as `Vec<f64>` or `(f64, f64)`.
+    pub fn results<O: FromResults>(&self) -> Option<O> {
+        O::from_results(
+            self.circled
+                .iter()
+                .map(|&word| self.read::<Vec<char>>(word))
+                .collect(),
+        )
     }
 }
 
@@ -490,10 +917,35 @@ pub mod instructions {
         Instruction::Call(instructions, args.into_iter().map(Into::into).collect())
     }
 
+    pub fn fork<A>(calls: Vec<(Vec<Instruction>, Vec<A>)>) -> Instruction
+    where
+        A: Into<Word>,
+    {
+        Instruction::Fork(
+            calls
+                .into_iter()
+                .map(|(instructions, args)| {
+                    (instructions, args.into_iter().map(Into::into).collect())
+                })
+                .collect(),
+        )
+    }
+
+    pub fn join() -> Instruction {
+        Instruction::Join
+    }
+
     pub fn circle(word: impl Into<Word>) -> Instruction {
         Instruction::Circle(word.into())
     }
 
+    pub fn circle_all<A>(words: Vec<A>) -> Instruction
+    where
+        A: Into<Word>,
+    {
+        Instruction::CircleAll(words.into_iter().map(Into::into).collect())
+    }
+
     pub fn copy(word: impl Into<Word>) -> Instruction {
         Instruction::Copy(word.into())
     }
@@ -536,6 +988,14 @@ pub mod instructions {
         Instruction::Mod(a.into(), b.into())
     }
 
+    pub fn mul(a: impl Into<Word>, b: impl Into<Word>) -> Instruction {
+        Instruction::Mul(a.into(), b.into())
+    }
+
+    pub fn div(a: impl Into<Word>, b: impl Into<Word>) -> Instruction {
+        Instruction::Div(a.into(), b.into())
+    }
+
     pub fn stop() -> Instruction {
         Instruction::Stop
     }
@@ -551,4 +1011,61 @@ pub mod instructions {
     pub fn jump_rel_if_str(a: impl Into<Word>, string: &str, jump: i64) -> Instruction {
         Instruction::JumpRelIfStr(a.into(), string.to_string(), jump)
     }
+
+    pub fn jump_rel_if_cond(condition: Condition, jump: i64) -> Instruction {
+        Instruction::JumpRelIfCond(condition, jump)
+    }
+
+    pub fn jump_if_empty(word: impl Into<Word>, jump: i64) -> Instruction {
+        jump_rel_if_cond(empty(word), jump)
+    }
+
+    pub fn jump_if_not_empty(word: impl Into<Word>, jump: i64) -> Instruction {
+        jump_rel_if_cond(empty(word).negate(), jump)
+    }
+
+    pub fn jump_if_contains(word: impl Into<Word>, c: char, jump: i64) -> Instruction {
+        jump_rel_if_cond(contains(word, c), jump)
+    }
+
+    pub fn jump_if_numeric(word: impl Into<Word>, jump: i64) -> Instruction {
+        jump_rel_if_cond(numeric(word), jump)
+    }
+
+    pub fn jump_rel_cmp_str(
+        a: impl Into<Word>,
+        b: impl Into<Word>,
+        ordering: Ordering,
+        jump: i64,
+    ) -> Instruction {
+        jump_rel_if_cond(cmp_str(a, b, ordering), jump)
+    }
+
+    pub fn empty(word: impl Into<Word>) -> Condition {
+        Condition::Empty(word.into())
+    }
+
+    pub fn contains(word: impl Into<Word>, c: char) -> Condition {
+        Condition::Contains(word.into(), c)
+    }
+
+    pub fn numeric(word: impl Into<Word>) -> Condition {
+        Condition::Numeric(word.into())
+    }
+
+    pub fn cmp_val(word: impl Into<Word>, ordering: Ordering, val: f64) -> Condition {
+        Condition::CmpVal(word.into(), ordering, val)
+    }
+
+    pub fn cmp(a: impl Into<Word>, b: impl Into<Word>, ordering: Ordering) -> Condition {
+        Condition::Cmp(a.into(), b.into(), ordering)
+    }
+
+    pub fn cmp_str(a: impl Into<Word>, b: impl Into<Word>, ordering: Ordering) -> Condition {
+        Condition::CmpStr(a.into(), b.into(), ordering)
+    }
+
+    pub fn equals_str(word: impl Into<Word>, string: &str) -> Condition {
+        Condition::EqualsStr(word.into(), string.to_string())
+    }
 }
@@ -62,6 +62,51 @@ pub fn modulo_prog() -> Vec<Instruction> {
     ]
 }
 
+pub fn divmod_prog() -> Vec<Instruction> {
+    vec![
+        // r, q, b, 1
+        write("\n"),
+        copy((0, -1, CPF)),
+        write(0.),
+        copy((-CPFI, -1, CPF)),
+        write(1.),
+        write("\n"),
+        // :start
+        jump_rel_cmp((0, -1, CPF), (2 * CPFI, -1, CPF), Ordering::Less, 7),
+        // r := r - b
+        sub((0, -1, CPF), (2 * CPFI, -1, CPF)),
+        // q := q + 1
+        add((0, -1, CPF), (2 * CPFI, -1, CPF)),
+        copy((0, -1, CPF)),
+        copy((0, -1, CPF)),
+        write("\n"),
+        jump(-6),
+        // quotient and remainder
+        circle_all(vec![(CPFI, -1, CPF), (0, -1, CPF)]),
+    ]
+}
+
+pub fn add_prog() -> Vec<Instruction> {
+    vec![
+        write("\n"),
+        add((0, -1, CPF), (CPFI, -1, CPF)),
+        circle((-CPFI, 0, CPF)),
+    ]
+}
+
+/// Computes `a + b` and `c + d` on two sheets at the same time.
+pub fn parallel_sums() -> Vec<Instruction> {
+    vec![
+        write("\n"),
+        fork(vec![
+            (add_prog(), vec![(0, -1, CPF), (CPFI, -1, CPF)]),
+            (add_prog(), vec![(2 * CPFI, -1, CPF), (3 * CPFI, -1, CPF)]),
+        ]),
+        join(),
+        circle_all(vec![(-2 * CPFI, 0, CPF), (-CPFI, 0, CPF)]),
+    ]
+}
+
 pub fn gcd_with_mod() -> Vec<Instruction> {
     vec![
         write("\n         b"),
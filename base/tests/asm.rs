@@ -0,0 +1,82 @@
+//! Programs in the text format against the ones built in Rust.
+
+use papier::{
+    asm::{parse_program, ParseError},
+    papervm::{CharCell, Instruction, PaperVM},
+    programs::{gcd_with_mod, parallel_sums},
+};
+
+const GCD: &str = include_str!("../programs/gcd.papier");
+const PARALLEL_SUMS: &str = include_str!("../programs/parallel_sums.papier");
+
+fn run(program: Vec<Instruction>, args: &[f64]) -> (String, Vec<f64>) {
+    let mut vm = PaperVM::<CharCell>::new(program);
+    for arg in args {
+        vm.write(arg);
+    }
+    while !vm.step().is_finished() {}
+    (vm.print(), vm.results::<Vec<f64>>().unwrap())
+}
+
+fn error(source: &str) -> ParseError {
+    parse_program(source).unwrap_err()
+}
+
+#[test]
+fn gcd_matches_the_built_in_program() {
+    let program = parse_program(GCD).unwrap();
+    assert_eq!(format!("{:?}", program), format!("{:?}", gcd_with_mod()));
+    assert_eq!(
+        run(program, &[1123., 127.]),
+        run(gcd_with_mod(), &[1123., 127.])
+    );
+}
+
+#[test]
+fn parallel_sums_matches_the_built_in_program() {
+    let program = parse_program(PARALLEL_SUMS).unwrap();
+    assert_eq!(format!("{:?}", program), format!("{:?}", parallel_sums()));
+    let (sheet, results) = run(program, &[1., 2., 30., 40.]);
+    assert_eq!(results, vec![3., 70.]);
+    assert_eq!((sheet, results), run(parallel_sums(), &[1., 2., 30., 40.]));
+}
+
+#[test]
+fn unknown_instruction() {
+    let e = error("write 1\n\nwrit 2\n");
+    assert_eq!(e.line, 3);
+    assert_eq!(e.message, "unknown instruction `writ`");
+
+    // Inside a subprogram the line of the call is given, and the line in it
+    let e = error("prog p\n  circl (0, 0, 1)\nend\ncall p\n");
+    assert_eq!(e.line, 4);
+    assert_eq!(e.message, "in `p`: line 2: unknown instruction `circl`");
+}
+
+#[test]
+fn unterminated_prog() {
+    let e = error("write 1\nprog add\n  write \"\\n\"\n");
+    assert_eq!(e.line, 2);
+    assert_eq!(e.message, "program `add` has no `end`");
+}
+
+#[test]
+fn bad_operand() {
+    let e = error("write 1\nadd (0, -1, 10) 3\n");
+    assert_eq!(e.line, 2);
+    assert_eq!(e.message, "expected word, found 3");
+
+    let e = error("jump_rel_if (0, 0, 10) ~ 0 3");
+    assert_eq!(e.line, 1);
+
+    let e = error("circle (0, 0, 10) (1, 0, 10)");
+    assert_eq!(e.line, 1);
+    assert_eq!(e.message, "expected end of line, found (1, 0, 10)");
+}
+
+#[test]
+fn stray_character() {
+    let e = error("write 1 )\n");
+    assert_eq!(e.line, 1);
+    assert_eq!(e.message, "unexpected `)`");
+}
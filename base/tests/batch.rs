@@ -0,0 +1,100 @@
+//! Batches of runs on several threads and their result tables.
+
+use papier::{
+    batch::{run_batch, BatchConfig, BatchError},
+    convenience::call_static,
+    papervm::{instructions::*, CharCell, CHARS_PER_FLOAT},
+    programs::gcd_with_mod,
+};
+
+fn config(threads: usize, max_steps: u64) -> BatchConfig {
+    BatchConfig { threads, max_steps }
+}
+
+#[test]
+fn rows_are_in_input_order() {
+    // Runs take very different numbers of steps, so they finish out of order
+    let inputs: Vec<(f64, f64)> = (1..=24)
+        .map(|i| (if i % 2 == 0 { 1123. } else { 6. }, 3. + i as f64))
+        .collect();
+    let table = run_batch::<CharCell, _, _>(
+        |&(a, b)| call_static(gcd_with_mod(), vec![a, b], CHARS_PER_FLOAT),
+        &inputs,
+        &config(4, 1_000_000),
+    );
+
+    assert_eq!(table.rows.len(), inputs.len());
+    for (row, input) in table.rows.iter().zip(&inputs) {
+        assert_eq!(row.input, format!("{:?}", input));
+        assert!(row.error.is_none(), "{:?}: {:?}", input, row.error);
+        let gcd = |mut a: u64, mut b: u64| {
+            while b != 0 {
+                (a, b) = (b, a % b);
+            }
+            a
+        };
+        let expected = gcd(input.0 as u64, input.1 as u64).to_string();
+        assert_eq!(row.results, vec![expected]);
+        assert!(row.sheets > 1);
+    }
+}
+
+#[test]
+fn panics_are_reported_per_row() {
+    let inputs = [1, 2, 3];
+    let table = run_batch::<CharCell, _, _>(
+        |&i| {
+            if i == 2 {
+                panic!("no program for {}", i);
+            }
+            vec![
+                write(i as f64),
+                circle((-(CHARS_PER_FLOAT as i64), 0, CHARS_PER_FLOAT)),
+            ]
+        },
+        &inputs,
+        &config(2, 100),
+    );
+
+    assert!(table.rows[0].error.is_none());
+    assert!(matches!(
+        &table.rows[1].error,
+        Some(BatchError::Panic(msg)) if msg == "no program for 2"
+    ));
+    assert_eq!(table.rows[2].results, vec!["3"]);
+    assert_eq!(table.stats().errors, 1);
+}
+
+#[test]
+fn step_limit_stops_a_run() {
+    let table = run_batch::<CharCell, _, _>(|_| vec![jump(0)], &[()], &config(1, 50));
+    let row = &table.rows[0];
+    assert!(matches!(row.error, Some(BatchError::StepLimit(50))));
+    assert_eq!(row.steps, 50);
+    assert!(row.results.is_empty());
+}
+
+#[test]
+fn fields_are_quoted() {
+    let table = run_batch::<CharCell, _, _>(|_| panic!("a, \"b\"\nc"), &["x,y"], &config(1, 10));
+
+    let csv = table.to_csv();
+    let row = csv.lines().skip(1).collect::<Vec<_>>().join("\n");
+    assert!(
+        row.starts_with(
+            r#""""x,y""",,"panicked: a, ""b""
+c",0,0,0,"#
+        ),
+        "{}",
+        csv
+    );
+
+    let json = table.to_json();
+    assert!(
+        json.contains(
+            r#"{"input":"\"x,y\"","results":[],"error":"panicked: a, \"b\"\nc","steps":0,"#
+        ),
+        "{}",
+        json
+    );
+}
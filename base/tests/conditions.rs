@@ -0,0 +1,127 @@
+//! Conditions of `JumpRelIfCond` on words of a sheet, and their text format.
+
+use std::cmp::Ordering;
+
+use papier::{
+    asm::parse_program,
+    listing::mnemonic,
+    papervm::{instructions::*, CharCell, Condition, Instruction, PaperVM, CHARS_PER_FLOAT},
+};
+
+const CPF: usize = CHARS_PER_FLOAT;
+const CPFI: i64 = CHARS_PER_FLOAT as i64;
+
+/// `12`, `3`, `apple` and `pear` next to each other, with the cursor after
+/// them.
+fn sheet_with(program: Vec<Instruction>) -> PaperVM<CharCell> {
+    let mut vm = PaperVM::new(program);
+    vm.write(&12.);
+    vm.write(&3.);
+    vm.write(&"     apple");
+    vm.write(&"     pear ");
+    vm
+}
+
+const TWELVE: (i64, i64, usize) = (-4 * CPFI, 0, CPF);
+const THREE: (i64, i64, usize) = (-3 * CPFI, 0, CPF);
+const APPLE: (i64, i64, usize) = (-2 * CPFI, 0, CPF);
+const PEAR: (i64, i64, usize) = (-CPFI, 0, CPF);
+const BLANK: (i64, i64, usize) = (0, 1, CPF);
+
+fn holds(condition: Condition) -> bool {
+    sheet_with(vec![]).holds(&condition)
+}
+
+#[test]
+fn contains_and_numeric() {
+    assert!(holds(contains(APPLE, 'p')));
+    assert!(!holds(contains(APPLE, 'z')));
+    assert!(holds(contains(TWELVE, '1')));
+
+    assert!(holds(numeric(TWELVE)));
+    assert!(!holds(numeric(APPLE)));
+    assert!(!holds(numeric(BLANK)));
+    assert!(holds(empty(BLANK)));
+}
+
+#[test]
+fn comparisons() {
+    assert!(holds(cmp(TWELVE, THREE, Ordering::Greater)));
+    assert!(!holds(cmp(TWELVE, THREE, Ordering::Less)));
+    assert!(holds(cmp_val(THREE, Ordering::Equal, 3.)));
+
+    // Text compares without the surrounding whitespace
+    assert!(holds(cmp_str(APPLE, PEAR, Ordering::Less)));
+    assert!(!holds(cmp_str(APPLE, PEAR, Ordering::Greater)));
+    assert!(holds(cmp_str(PEAR, PEAR, Ordering::Equal)));
+    // By text, not by value
+    assert!(holds(cmp_str(TWELVE, THREE, Ordering::Less)));
+    assert!(holds(equals_str(PEAR, "pear")));
+}
+
+#[test]
+fn not_and_or() {
+    let yes = || numeric(TWELVE);
+    let no = || numeric(APPLE);
+
+    assert!(holds(no().negate()));
+    assert!(!holds(yes().negate()));
+    assert!(holds(yes().and(yes())));
+    assert!(!holds(yes().and(no())));
+    assert!(!holds(no().and(yes())));
+    assert!(holds(no().or(yes())));
+    assert!(!holds(no().or(no())));
+    assert!(holds(no().or(yes()).and(no().negate())));
+}
+
+/// Whether a `JumpRelIfCond` on the sheet jumps.
+fn jumps(condition: Condition) -> bool {
+    let mut vm = sheet_with(vec![
+        jump_rel_if_cond(condition, 3),
+        write("n"),
+        jump(2),
+        write("y"),
+        circle((-1, 0, 1usize)),
+    ]);
+    while !vm.step().is_finished() {}
+    match vm.result::<Vec<char>>().as_deref() {
+        Some(['y']) => true,
+        Some(['n']) => false,
+        other => panic!("circled {:?}", other),
+    }
+}
+
+#[test]
+fn jump_rel_if_cond_taken() {
+    assert!(jumps(contains(APPLE, 'a')));
+    assert!(!jumps(contains(PEAR, 'a').negate()));
+    assert!(jumps(empty(BLANK).and(numeric(THREE))));
+    assert!(!jumps(empty(BLANK).and(numeric(PEAR))));
+}
+
+#[test]
+fn conditions_in_text() {
+    let conditions = [
+        empty(BLANK),
+        contains(APPLE, 'p'),
+        numeric(TWELVE),
+        cmp_val(THREE, Ordering::Less, -2.5),
+        cmp(TWELVE, THREE, Ordering::Equal),
+        cmp_str(APPLE, PEAR, Ordering::Greater),
+        equals_str(PEAR, "pear"),
+        numeric(TWELVE)
+            .negate()
+            .and(empty(BLANK).or(contains(PEAR, 'r'))),
+    ];
+    for condition in conditions {
+        let instruction = jump_rel_if_cond(condition, -4);
+        let text = mnemonic(&instruction);
+        let parsed = parse_program(&text).unwrap_or_else(|e| panic!("{}: {}", text, e));
+        assert_eq!(format!("{:?}", parsed), format!("{:?}", [instruction]));
+    }
+
+    let parsed =
+        parse_program("jump_rel_if_cond and not empty (0, -1, 10) numeric (0, -1, 10) 3").unwrap();
+    let expected = jump_rel_if_cond(empty((0, -1, CPF)).negate().and(numeric((0, -1, CPF))), 3);
+    assert_eq!(format!("{:?}", parsed), format!("{:?}", [expected]));
+}
@@ -0,0 +1,85 @@
+//! Listings of programs, which read back in as the same program.
+
+use std::cmp::Ordering;
+
+use papier::{
+    asm::parse_program,
+    listing::{listing, mnemonic, ListingOptions},
+    papervm::{instructions::*, Instruction, CHARS_PER_FLOAT},
+    programs::add_prog,
+};
+
+const CPF: usize = CHARS_PER_FLOAT;
+
+/// The instructions of a listing without their indices and jump targets.
+fn source(listing: &str) -> String {
+    listing
+        .lines()
+        .filter(|line| !line.ends_with(':'))
+        .map(|line| {
+            let (_, instruction) = line.trim_start().split_once("  ").unwrap();
+            match instruction.rsplit_once("  -> ") {
+                Some((instruction, _)) => instruction,
+                None => instruction,
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn assert_reads_back(program: Vec<Instruction>) {
+    let text = listing(&program, &ListingOptions::default());
+    let parsed = parse_program(&source(&text)).unwrap_or_else(|e| panic!("{}\n{}", text, e));
+    let mnemonics = |program: &[Instruction]| program.iter().map(mnemonic).collect::<Vec<_>>();
+    assert_eq!(mnemonics(&parsed), mnemonics(&program));
+    assert_eq!(format!("{:?}", parsed), format!("{:?}", program));
+}
+
+#[test]
+fn listing_reads_back() {
+    // Called programs are only listed by their length, so this has none
+    assert_reads_back(add_prog());
+    assert_reads_back(vec![
+        write("\n"),
+        write("say \"hi\"\\"),
+        write("tab\tand é'"),
+        move_cursor(-3, 2),
+        copy_trimmed((0, -1, CPF)),
+        jump_rel_if_str((0, -1, CPF), "a \"b\"", 2),
+        jump_rel_if_cond(
+            contains((0, -1, CPF), '"').or(equals_str((0, -1, CPF), "x\ny")),
+            -1,
+        ),
+        jump_rel_cmp((0, -1, CPF), (10, -1, CPF), Ordering::Less, -6),
+        stop(),
+    ]);
+}
+
+#[test]
+fn jump_targets() {
+    let program = vec![
+        jump(2),
+        write("a"),
+        jump_rel_if((0, 0, CPF), Ordering::Less, 0., -2),
+        jump(5),
+    ];
+
+    assert_eq!(
+        listing(&program, &ListingOptions::default()),
+        "   0  jump 2  -> 2
+   1  write \"a\"
+   2  jump_rel_if (0, 0, 10) < 0 -2  -> 0
+   3  jump 5  -> 8 (outside program)
+"
+    );
+    assert_eq!(
+        listing(&program, &ListingOptions { labels: true }),
+        "L0:
+   0  jump 2  -> L1
+   1  write \"a\"
+L1:
+   2  jump_rel_if (0, 0, 10) < 0 -2  -> L0
+   3  jump 5  -> 8 (outside program)
+"
+    );
+}
@@ -0,0 +1,114 @@
+//! The peephole optimizer keeps every sheet the same.
+
+use papier::{
+    listing::jump_offset,
+    optimize::{optimize, optimize_verified, verify, OptimizeOptions},
+    papervm::{instructions::*, CharCell, Instruction, PaperVM, Word, CHARS_PER_FLOAT},
+    programs::{gcd_with_mod, parallel_sums},
+};
+
+const CPF: usize = CHARS_PER_FLOAT;
+const MAX_STEPS: u64 = 100_000;
+
+fn optimized(program: Vec<Instruction>) -> Vec<Instruction> {
+    optimize_verified::<CharCell>(program, &OptimizeOptions::default(), MAX_STEPS).unwrap()
+}
+
+fn nothing() -> Vec<Word> {
+    vec![]
+}
+
+/// The program with the arguments written by instructions first.
+fn with_args(program: &[Instruction], args: &[f64]) -> Vec<Instruction> {
+    let mut instructions: Vec<Instruction> = args.iter().map(|&arg| write(arg)).collect();
+    instructions.extend_from_slice(program);
+    instructions
+}
+
+fn results(program: Vec<Instruction>, args: &[f64]) -> Vec<f64> {
+    let mut vm = PaperVM::<CharCell>::new(program);
+    for arg in args {
+        vm.write(arg);
+    }
+    while !vm.step().is_finished() {}
+    vm.results::<Vec<f64>>().unwrap()
+}
+
+#[test]
+fn jumps_are_remapped() {
+    let program = vec![
+        move_cursor(0, 0),
+        write(""),
+        write("a"),
+        jump_if_empty((0, 1, CPF), 3),
+        write("b"),
+        move_cursor(0, 0),
+        circle_all(nothing()),
+    ];
+    let program = optimized(program);
+
+    assert_eq!(program.len(), 4);
+    // The no-ops in front of the jump and between it and its target are gone
+    assert_eq!(jump_offset(&program[1]), Some(2));
+    assert!(matches!(program[3], Instruction::CircleAll(_)));
+}
+
+#[test]
+fn jump_targets_are_not_merged() {
+    let program = vec![
+        jump_if_empty((0, 0, CPF), 2),
+        write("a"),
+        write("b"),
+        circle_all(nothing()),
+    ];
+    let program = optimized(program);
+    assert_eq!(program.len(), 4);
+    assert_eq!(jump_offset(&program[0]), Some(2));
+
+    // Without the jump they are written as one
+    let program = optimized(vec![write("a"), write("b"), circle_all(nothing())]);
+    assert_eq!(program.len(), 2);
+    assert_eq!(format!("{:?}", program[0]), format!("{:?}", write("ab")));
+}
+
+#[test]
+fn unreachable_code_is_removed() {
+    let program = vec![
+        write("a"),
+        jump(2),
+        write("dead"),
+        circle_all(nothing()),
+        write("after"),
+    ];
+    // The jump only skips dead code, so it goes as well
+    let program = optimized(program);
+    assert_eq!(program.len(), 2);
+    assert_eq!(format!("{:?}", program[0]), format!("{:?}", write("a")));
+}
+
+#[test]
+fn programs_write_the_same_sheets() {
+    for (program, args) in [
+        (gcd_with_mod(), vec![1123., 127.]),
+        (gcd_with_mod(), vec![48., 18.]),
+        (parallel_sums(), vec![1., 2., 30., 40.]),
+    ] {
+        let options = OptimizeOptions::default();
+        let optimized = optimize(program.clone(), &options);
+        verify::<CharCell>(
+            with_args(&program, &args),
+            with_args(&optimized, &args),
+            MAX_STEPS,
+        )
+        .unwrap();
+        assert_eq!(results(program, &args), results(optimized, &args));
+    }
+}
+
+#[test]
+fn verify_finds_changed_sheets() {
+    let original = vec![write("a"), circle_all(nothing())];
+    let changed = vec![write("b"), circle_all(nothing())];
+    let e = verify::<CharCell>(original, changed, MAX_STEPS).unwrap_err();
+    assert!(e.starts_with("sheet 0 differs"), "{}", e);
+}
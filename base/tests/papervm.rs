@@ -0,0 +1,149 @@
+//! Running sheets with calls and forks on the `PaperVM`.
+
+use papier::convenience::call_static_many;
+use papier::papervm::{
+    instructions::*, CharCell, Instruction, PaperVM, Pos, VmError, Word, WriteKind, Writer,
+    CHARS_PER_FLOAT,
+};
+use papier::programs::{add_prog, divmod_prog, parallel_sums};
+
+const CPF: usize = CHARS_PER_FLOAT;
+const CPFI: i64 = CHARS_PER_FLOAT as i64;
+const MAX_STEPS: usize = 10_000;
+
+fn run(program: Vec<Instruction>, args: &[f64]) -> (PaperVM<CharCell>, Result<(), VmError>) {
+    let mut vm = PaperVM::new(program);
+    for arg in args {
+        vm.write(arg);
+    }
+    for _ in 0..MAX_STEPS {
+        match vm.try_step() {
+            Ok(step) if step.is_finished() => return (vm, Ok(())),
+            Ok(_) => {}
+            Err(e) => return (vm, Err(e)),
+        }
+    }
+    panic!("step limit of {} reached", MAX_STEPS);
+}
+
+#[test]
+fn fork_join_copies_results_in_order() {
+    let (vm, result) = run(parallel_sums(), &[1., 2., 3., 4.]);
+    result.unwrap();
+    assert_eq!(vm.results::<(f64, f64)>(), Some((3., 7.)));
+    assert!(vm.forks.is_empty());
+    assert_eq!(vm.finished_papers.len(), 2);
+    for (sheet, sum) in vm.finished_papers.iter().zip([3., 7.]) {
+        assert!(sheet.is_finished());
+        assert_eq!(sheet.clone().result::<f64>(), Some(sum));
+        assert_eq!(sheet.call_site.as_ref().unwrap().results.len(), 1);
+    }
+}
+
+#[test]
+fn call_returns_several_results() {
+    let program = vec![
+        write("\n"),
+        call(divmod_prog(), vec![(0, -1, CPF), (CPFI, -1, CPF)]),
+        circle_all(vec![(-2 * CPFI, 0, CPF), (-CPFI, 0, CPF)]),
+    ];
+    let (vm, result) = run(program, &[17., 5.]);
+    result.unwrap();
+    assert_eq!(vm.results::<(f64, f64)>(), Some((3., 2.)));
+    let call_site = vm.finished_papers[0].call_site.as_ref().unwrap();
+    assert_eq!(call_site.results.len(), 2);
+
+    let program = call_static_many(divmod_prog(), vec![23., 4.], vec![CPF, CPF]);
+    let (vm, result) = run(program, &[]);
+    result.unwrap();
+    assert_eq!(vm.results::<Vec<f64>>(), Some(vec![5., 3.]));
+}
+
+#[test]
+fn circling_before_join_is_an_error() {
+    let program = vec![
+        write("\n"),
+        fork(vec![(add_prog(), vec![(0, -1, CPF), (CPFI, -1, CPF)])]),
+        circle((0, -1, CPF)),
+    ];
+    let (vm, result) = run(program, &[1., 2.]);
+    assert_eq!(result, Err(VmError::UnjoinedForks(1)));
+    assert!(!vm.is_finished());
+}
+
+#[test]
+fn circling_nothing_finishes() {
+    let (vm, result) = run(vec![write(1.), circle_all(Vec::<Word>::new())], &[]);
+    result.unwrap();
+    assert!(vm.is_finished());
+    assert_eq!(vm.results::<Vec<f64>>(), Some(vec![]));
+
+    // A call that returns nothing lets its caller go on
+    let program = vec![
+        call(vec![circle_all(Vec::<Word>::new())], Vec::<Word>::new()),
+        write(2.),
+        circle((-CPFI, 0, CPF)),
+    ];
+    let (mut vm, result) = run(program, &[]);
+    result.unwrap();
+    assert_eq!(vm.finished_papers.len(), 1);
+    assert_eq!(vm.result::<f64>(), Some(2.));
+}
+
+/// The writer of the cells of the word, which have to share one.
+fn writer_of(vm: &PaperVM<CharCell>, x: i64, y: i64) -> Writer {
+    let writers = vm.get_writers();
+    let writer = writers[&Pos(x, y)];
+    for i in 1..CPFI {
+        assert_eq!(writers[&Pos(x + i, y)], writer, "({}, {})", x + i, y);
+    }
+    writer
+}
+
+fn by(kind: WriteKind, index: usize) -> Writer {
+    Writer {
+        kind,
+        index: Some(index),
+    }
+}
+
+#[test]
+fn writers_of_cells() {
+    let two = || vec![(-4 * CPFI, -1, CPF), (-3 * CPFI, -1, CPF)];
+    let program = vec![
+        write("\n"),
+        copy((0, -1, CPF)),
+        add((-CPFI, -1, CPF), (0, -1, CPF)),
+        write(7.),
+        call(add_prog(), vec![(-3 * CPFI, -1, CPF), (-2 * CPFI, -1, CPF)]),
+        fork(vec![(add_prog(), two()), (add_prog(), two())]),
+        join(),
+        circle((-CPFI, 0, CPF)),
+    ];
+    let (vm, result) = run(program, &[1., 2.]);
+    result.unwrap();
+
+    let argument = Writer {
+        kind: WriteKind::Argument,
+        index: None,
+    };
+    assert_eq!(writer_of(&vm, 0, 0), argument);
+    assert_eq!(writer_of(&vm, CPFI, 0), argument);
+    assert_eq!(writer_of(&vm, 0, 1), by(WriteKind::Copy, 1));
+    assert_eq!(writer_of(&vm, CPFI, 1), by(WriteKind::Arithmetic, 2));
+    assert_eq!(writer_of(&vm, 2 * CPFI, 1), by(WriteKind::Write, 3));
+    // The call is finished a step after the counter went past it, the join
+    // right away
+    assert_eq!(writer_of(&vm, 3 * CPFI, 1), by(WriteKind::Result, 4));
+    assert_eq!(writer_of(&vm, 4 * CPFI, 1), by(WriteKind::Result, 6));
+    assert_eq!(writer_of(&vm, 5 * CPFI, 1), by(WriteKind::Result, 6));
+    assert_eq!(vm.results::<Vec<f64>>(), Some(vec![3.]));
+
+    // Called and forked sheets get their values as arguments
+    assert_eq!(vm.finished_papers.len(), 3);
+    for sheet in &vm.finished_papers {
+        assert_eq!(writer_of(sheet, 0, 0), argument);
+        assert_eq!(writer_of(sheet, CPFI, 0), argument);
+        assert_eq!(writer_of(sheet, 0, 1), by(WriteKind::Arithmetic, 1));
+    }
+}
@@ -0,0 +1,570 @@
+//! Checks which cells of a stacker program hold text and which numbers.
+//!
+//! `text` writes text and everything else numbers, but only numbers can be
+//! added or compared to numbers: text reads as 0. The checker follows the
+//! cursor through the program like the [interpreter](crate::interpret) does,
+//! without knowing any values, so both ways of every condition are taken.
+//! What a cell may hold is collected over all of them, which finds text
+//! where a number is expected, comparisons of text with numbers and reads of
+//! cells nothing writes.
+//!
+//! Jumps back are followed a few times, and loops are run twice, which is
+//! enough for rows that read the row the previous iteration wrote.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Display};
+
+use crate::stacker::{Cell, Cond, Function, Program, SourceLoc, StackInstr};
+
+/// How often the same instruction is checked in one run of a block
+const MAX_VISITS: usize = 8;
+/// How often the body of a loop is checked
+const ITERATIONS: usize = 2;
+
+/// What a cell may hold
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Kinds {
+    pub text: bool,
+    pub number: bool,
+}
+
+impl Kinds {
+    const TEXT: Kinds = Kinds {
+        text: true,
+        number: false,
+    };
+    const NUMBER: Kinds = Kinds {
+        text: false,
+        number: true,
+    };
+
+    fn join(self, other: Kinds) -> Kinds {
+        Kinds {
+            text: self.text || other.text,
+            number: self.number || other.number,
+        }
+    }
+
+    fn is_empty(self) -> bool {
+        !self.text && !self.number
+    }
+
+    fn is_single(self) -> bool {
+        self.text != self.number
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    /// Arithmetic with a cell that can hold text
+    TextArithmetic(Cell),
+    /// Text compared with a number
+    Mismatched(Cell, Cell),
+    /// Text compared with a value
+    TextComparedTo(Cell, f64),
+    /// A cell read before anything is written in it, or a variable before
+    /// it is set
+    NeverWritten(Cell),
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::TextArithmetic(cell) => write!(f, "arithmetic on text in {}", cell),
+            Problem::Mismatched(a, b) => write!(f, "{} and {} compare text with a number", a, b),
+            Problem::TextComparedTo(cell, val) => {
+                write!(f, "text in {} is compared with {}", cell, val)
+            }
+            Problem::NeverWritten(cell) => write!(f, "{} is read but never written", cell),
+        }
+    }
+}
+
+/// A problem with the instruction it was found in. Instructions of a called
+/// function are in the lines of the function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeError {
+    pub function: Option<String>,
+    pub loc: SourceLoc,
+    pub problem: Problem,
+}
+
+impl Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(function) = &self.function {
+            write!(f, "in `{}`: ", function)?;
+        }
+        write!(
+            f,
+            "{}:{} `{}`: {}",
+            self.loc.line, self.loc.column, self.loc.instruction, self.problem
+        )
+    }
+}
+
+/// Where the cursor is and what the variables may hold
+#[derive(Debug, Clone, PartialEq)]
+struct State {
+    row: i64,
+    col: i64,
+    vars: HashMap<String, Kinds>,
+}
+
+impl State {
+    fn new() -> State {
+        State {
+            row: 0,
+            col: 0,
+            vars: HashMap::new(),
+        }
+    }
+
+    fn newline(&mut self) {
+        self.row += 1;
+        self.col = 0;
+    }
+}
+
+/// Adds a state, joining the variables of one with the cursor on the same
+/// cell.
+fn merge(states: &mut Vec<State>, state: State) {
+    let same = states
+        .iter_mut()
+        .find(|other| (other.row, other.col) == (state.row, state.col));
+    match same {
+        Some(other) => {
+            for (name, kinds) in state.vars {
+                let joined = other.vars.get(&name).map_or(kinds, |k| k.join(kinds));
+                other.vars.insert(name, joined);
+            }
+        }
+        None => states.push(state),
+    }
+}
+
+/// The ways a block can be left
+#[derive(Default)]
+struct Outcome {
+    done: Vec<State>,
+    breaks: Vec<State>,
+    returns: Kinds,
+}
+
+impl Outcome {
+    fn add(&mut self, other: Outcome) {
+        for state in other.done {
+            merge(&mut self.done, state);
+        }
+        for state in other.breaks {
+            merge(&mut self.breaks, state);
+        }
+        self.returns = self.returns.join(other.returns);
+    }
+}
+
+enum Unit<'a> {
+    Instr(&'a StackInstr),
+    LineEnd,
+}
+
+struct Checker<'a> {
+    functions: &'a HashMap<String, Function>,
+    /// What every cell of every sheet may hold, by sheet
+    cells: HashMap<(usize, i64, i64), Kinds>,
+    /// Whether `cells` grew in this round
+    changed: bool,
+    /// Sheets handed out in this round
+    sheets: usize,
+    problems: Vec<TypeError>,
+    function: Option<String>,
+    loc: Option<SourceLoc>,
+    /// Functions being checked, to stop at recursive calls
+    calls: Vec<String>,
+}
+
+impl<'a> Checker<'a> {
+    fn report(&mut self, problem: Problem) {
+        let Some(loc) = self.loc.clone() else {
+            return;
+        };
+        let error = TypeError {
+            function: self.function.clone(),
+            loc,
+            problem,
+        };
+        if !self.problems.contains(&error) {
+            self.problems.push(error);
+        }
+    }
+
+    fn write(&mut self, sheet: usize, state: &mut State, kinds: Kinds) {
+        if !kinds.is_empty() {
+            let cell = self.cells.entry((sheet, state.row, state.col)).or_default();
+            let joined = cell.join(kinds);
+            if joined != *cell {
+                *cell = joined;
+                self.changed = true;
+            }
+        }
+        state.col += 1;
+    }
+
+    /// What the cell may hold, `None` if nothing is ever written in it
+    fn kinds(&self, sheet: usize, state: &State, cell: &Cell) -> Option<Kinds> {
+        match cell {
+            Cell::Rel(pos) => {
+                let at = (sheet, state.row + pos.y as i64, state.col + pos.x as i64);
+                self.cells.get(&at).copied()
+            }
+            Cell::Var(name) => state.vars.get(name).copied(),
+        }
+    }
+
+    /// Reads a cell, reporting it if it is never written.
+    fn read(&mut self, sheet: usize, state: &State, cell: &Cell) -> Option<Kinds> {
+        let kinds = self.kinds(sheet, state, cell);
+        if kinds.is_none() {
+            self.report(Problem::NeverWritten(cell.clone()));
+        }
+        kinds
+    }
+
+    fn number(&mut self, sheet: usize, state: &State, cell: &Cell) {
+        if let Some(kinds) = self.read(sheet, state, cell) {
+            if kinds.text {
+                self.report(Problem::TextArithmetic(cell.clone()));
+            }
+        }
+    }
+
+    fn compare(&mut self, sheet: usize, state: &State, a: &Cell, b: &Cell) {
+        let (Some(ka), Some(kb)) = (self.read(sheet, state, a), self.read(sheet, state, b)) else {
+            return;
+        };
+        // Cells that may hold either are only reported against ones that
+        // can't
+        if ka != kb && (ka.is_single() || kb.is_single()) {
+            self.report(Problem::Mismatched(a.clone(), b.clone()));
+        }
+    }
+
+    fn compare_to(&mut self, sheet: usize, state: &State, cell: &Cell, val: f64) {
+        if let Some(kinds) = self.read(sheet, state, cell) {
+            if kinds.text {
+                self.report(Problem::TextComparedTo(cell.clone(), val));
+            }
+        }
+    }
+
+    /// Checks the cells of a condition, and returns whether it holds if that
+    /// is known without values: cells nothing writes in are always empty.
+    fn cond(&mut self, sheet: usize, state: &State, cond: &Cond) -> Option<bool> {
+        match cond {
+            Cond::Cmp(a, _, b) => self.compare(sheet, state, a, b),
+            Cond::CmpVal(cell, _, val) => self.compare_to(sheet, state, cell, *val),
+            // Looking for empty cells is what this is for
+            Cond::Empty(cell) => return self.kinds(sheet, state, cell).is_none().then_some(true),
+            Cond::Not(cond) => return self.cond(sheet, state, cond).map(|holds| !holds),
+        }
+        None
+    }
+
+    /// Checks a called program on a new sheet with the inputs on its first
+    /// row, and returns what it may return.
+    fn call(
+        &mut self,
+        inputs: Vec<Kinds>,
+        params: Option<&[String]>,
+        body: &[Vec<StackInstr>],
+        function: Option<String>,
+    ) -> Kinds {
+        let sheet = self.sheets;
+        self.sheets += 1;
+
+        let mut state = State::new();
+        for &kinds in &inputs {
+            self.write(sheet, &mut state, kinds);
+        }
+        if let Some(params) = params {
+            state.vars = params.iter().cloned().zip(inputs).collect();
+        }
+        state.newline();
+
+        let outer = (self.function.take(), self.loc.take());
+        self.function = function;
+        let returns = self.lines(sheet, body, vec![state]);
+        (self.function, self.loc) = outer;
+        returns
+    }
+
+    /// What a value instruction may write, `None` for other instructions
+    fn value(&mut self, sheet: usize, state: &State, instruction: &StackInstr) -> Option<Kinds> {
+        Some(match instruction {
+            StackInstr::Text(_) => Kinds::TEXT,
+            StackInstr::Write(_) => Kinds::NUMBER,
+            StackInstr::Copy(cell) => self.read(sheet, state, cell).unwrap_or_default(),
+            StackInstr::Add(a, b)
+            | StackInstr::Sub(a, b)
+            | StackInstr::Mod(a, b)
+            | StackInstr::Mul(a, b)
+            | StackInstr::Div(a, b) => {
+                self.number(sheet, state, a);
+                self.number(sheet, state, b);
+                Kinds::NUMBER
+            }
+            StackInstr::Call { substack, inputs } => {
+                let inputs = inputs
+                    .iter()
+                    .map(|input| self.read(sheet, state, input).unwrap_or_default())
+                    .collect();
+                let function = self.loc.as_ref().map(|loc| {
+                    let outer = self
+                        .function
+                        .as_ref()
+                        .map_or(String::new(), |f| f.clone() + " ");
+                    format!("{}call at {}:{}", outer, loc.line, loc.column)
+                });
+                self.call(inputs, None, substack, function)
+            }
+            StackInstr::CallFn { name, args } => {
+                let inputs = args
+                    .iter()
+                    .map(|arg| self.read(sheet, state, arg).unwrap_or_default())
+                    .collect();
+                // Unknown functions and recursion are left to the compiler
+                let functions = self.functions;
+                match functions.get(name) {
+                    Some(function) if !self.calls.contains(name) => {
+                        self.calls.push(name.clone());
+                        let returns = self.call(
+                            inputs,
+                            Some(&function.params),
+                            &function.body,
+                            Some(name.clone()),
+                        );
+                        self.calls.pop();
+                        returns
+                    }
+                    _ => Kinds::default(),
+                }
+            }
+            _ => return None,
+        })
+    }
+
+    fn check(&mut self, sheet: usize, instruction: &StackInstr, mut state: State) -> Outcome {
+        let mut outcome = Outcome::default();
+        if let Some(kinds) = self.value(sheet, &state, instruction) {
+            self.write(sheet, &mut state, kinds);
+            outcome.done.push(state);
+            return outcome;
+        }
+
+        match instruction {
+            StackInstr::Set(name, value) => {
+                let kinds = self.value(sheet, &state, value).unwrap_or_default();
+                self.write(sheet, &mut state, kinds);
+                state.vars.insert(name.clone(), kinds);
+                outcome.done.push(state);
+            }
+            StackInstr::Ret(cell) => {
+                outcome.returns = self.read(sheet, &state, cell).unwrap_or_default();
+            }
+            StackInstr::Break => outcome.done.push(state),
+            StackInstr::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                let holds = self.cond(sheet, &state, cond);
+                if holds != Some(false) {
+                    outcome.add(self.block(sheet, then, false, vec![state.clone()], false));
+                }
+                if holds != Some(true) {
+                    outcome.add(self.block(sheet, otherwise, false, vec![state], false));
+                }
+            }
+            StackInstr::While { cond, body } => {
+                outcome = self.repeat(sheet, Some(cond), body, state);
+            }
+            StackInstr::Loop(body) => outcome = self.repeat(sheet, None, body, state),
+            StackInstr::BreakLoop => outcome.breaks.push(state),
+            _ => unreachable!("jumps are handled by the block"),
+        }
+        outcome
+    }
+
+    fn repeat(
+        &mut self,
+        sheet: usize,
+        cond: Option<&Cond>,
+        body: &[Vec<StackInstr>],
+        state: State,
+    ) -> Outcome {
+        let mut outcome = Outcome::default();
+        let mut states = vec![state];
+        for _ in 0..ITERATIONS {
+            states = self.enter(sheet, cond, states, &mut outcome);
+            let iteration = self.block(sheet, body, false, states, false);
+            outcome.returns = outcome.returns.join(iteration.returns);
+            for state in iteration.breaks {
+                merge(&mut outcome.done, state);
+            }
+            states = iteration.done;
+        }
+        // The states after the last iteration can still leave a `While`
+        self.enter(sheet, cond, states, &mut outcome);
+        outcome
+    }
+
+    /// The states that run the body of a loop, the others leave it.
+    fn enter(
+        &mut self,
+        sheet: usize,
+        cond: Option<&Cond>,
+        states: Vec<State>,
+        outcome: &mut Outcome,
+    ) -> Vec<State> {
+        let Some(cond) = cond else {
+            return states;
+        };
+        let mut running = vec![];
+        for state in states {
+            let holds = self.cond(sheet, &state, cond);
+            if holds != Some(false) {
+                running.push(state.clone());
+            }
+            if holds != Some(true) {
+                merge(&mut outcome.done, state);
+            }
+        }
+        running
+    }
+
+    /// Rows with a line end between them, and after the last one if
+    /// `end_row` is set, like [`Interpreter`](crate::interpret::Interpreter)
+    /// runs them. With `located` the rows are the lines of a program, and
+    /// problems are reported at the line and column of the instruction.
+    fn block(
+        &mut self,
+        sheet: usize,
+        rows: &[Vec<StackInstr>],
+        end_row: bool,
+        states: Vec<State>,
+        located: bool,
+    ) -> Outcome {
+        let mut units = vec![];
+        for (i, row) in rows.iter().enumerate() {
+            units.extend(
+                row.iter()
+                    .enumerate()
+                    .map(|(col, instr)| (i, col, Unit::Instr(instr))),
+            );
+            if end_row || i + 1 < rows.len() {
+                units.push((i, row.len(), Unit::LineEnd));
+            }
+        }
+
+        let mut outcome = Outcome::default();
+        let mut visits = vec![0; units.len()];
+        let mut work: VecDeque<(usize, State)> = states.into_iter().map(|s| (0, s)).collect();
+        while let Some((pc, mut state)) = work.pop_front() {
+            let Some((line, column, unit)) = units.get(pc) else {
+                merge(&mut outcome.done, state);
+                continue;
+            };
+            if visits[pc] == MAX_VISITS {
+                continue;
+            }
+            visits[pc] += 1;
+
+            let instruction = match unit {
+                Unit::Instr(instruction) => instruction,
+                Unit::LineEnd => {
+                    state.newline();
+                    work.push_back((pc + 1, state));
+                    continue;
+                }
+            };
+
+            if located {
+                self.loc = Some(SourceLoc {
+                    line: *line,
+                    column: *column,
+                    instruction: String::new(),
+                });
+            }
+            let outer = self.loc.clone();
+            if let Some(loc) = &mut self.loc {
+                loc.instruction = instruction.to_string();
+            }
+
+            let jump = match instruction {
+                StackInstr::Jump(rel) => Some((*rel, false)),
+                StackInstr::JumpRelIf(cell, _, val, rel) => {
+                    self.compare_to(sheet, &state, cell, *val);
+                    Some((*rel, true))
+                }
+                StackInstr::JumpEmpty(cell, rel) => {
+                    let empty = self.kinds(sheet, &state, cell).is_none();
+                    Some((*rel, !empty))
+                }
+                StackInstr::JumpRelCmp(a, b, _, rel) => {
+                    self.compare(sheet, &state, a, b);
+                    Some((*rel, true))
+                }
+                _ => None,
+            };
+            match jump {
+                Some((rel, conditional)) => {
+                    let target = pc as i64 + rel;
+                    if conditional {
+                        work.push_back((pc + 1, state.clone()));
+                    }
+                    // Jumps out of the block fail when the program runs
+                    if (0..=units.len() as i64).contains(&target) {
+                        work.push_back((target as usize, state));
+                    }
+                }
+                None => {
+                    let mut result = self.check(sheet, instruction, state);
+                    for state in std::mem::take(&mut result.done) {
+                        work.push_back((pc + 1, state));
+                    }
+                    outcome.add(result);
+                }
+            }
+            self.loc = outer;
+        }
+        outcome
+    }
+
+    /// Checks the lines of a program and returns what it may return.
+    fn lines(&mut self, sheet: usize, lines: &[Vec<StackInstr>], states: Vec<State>) -> Kinds {
+        self.block(sheet, lines, true, states, true).returns
+    }
+}
+
+/// Checks a program, with the values it is called with already written in
+/// its lines. Returns the problems in the order they are found.
+pub fn check_program(program: &Program) -> Vec<TypeError> {
+    let mut checker = Checker {
+        functions: &program.functions,
+        cells: HashMap::new(),
+        changed: true,
+        sheets: 0,
+        problems: vec![],
+        function: None,
+        loc: None,
+        calls: vec![],
+    };
+    // Cells copied from cells that are only found later get their kinds in
+    // the next round, the problems of the last round are the ones that hold
+    while checker.changed {
+        checker.changed = false;
+        checker.sheets = 1;
+        checker.problems.clear();
+        checker.lines(0, &program.lines, vec![State::new()]);
+    }
+    checker.problems
+}
@@ -0,0 +1,391 @@
+//! Runs stacker programs directly on rows of cells, without compiling them.
+//!
+//! This is the reference for what [`compile_stacker`](crate::stacker::compile_stacker)
+//! should do: a compiled program has to circle the same result, and without
+//! variables write the same sheet. Variables are kept by name here instead of
+//! in columns, their values are written at the cursor like any other value.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::rc::Rc;
+
+use papier::papervm::{compare_f64, FromChars, IntoChars, CHARS_PER_FLOAT as CPF};
+
+use crate::stacker::{Cell, Cond, Function, Program, StackInstr};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InterpretError {
+    StepLimit(u64),
+    /// The program ended without returning
+    RanOff,
+    /// Jumps can only go to instructions in the same block
+    JumpOutOfBlock(i64),
+    UnknownVar(String),
+    BreakOutsideLoop,
+    UnknownFunction(String),
+    /// A function called with a different number of values than it has
+    /// parameters
+    ArgCount(String),
+    /// Functions can't call themselves, like in compiled programs
+    Recursion(String),
+    /// A variable set to an instruction that writes nothing, like `x = break`
+    NoValue(String),
+}
+
+impl Display for InterpretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InterpretError::StepLimit(steps) => write!(f, "step limit of {} reached", steps),
+            InterpretError::RanOff => write!(f, "program ended without returning"),
+            InterpretError::JumpOutOfBlock(rel) => write!(f, "jump of {} leaves its block", rel),
+            InterpretError::UnknownVar(name) => {
+                write!(f, "variable `{}` is read before it is set", name)
+            }
+            InterpretError::BreakOutsideLoop => write!(f, "`BreakLoop` outside of a loop"),
+            InterpretError::UnknownFunction(name) => write!(f, "unknown function `{}`", name),
+            InterpretError::ArgCount(name) => {
+                write!(f, "`{}` is called with the wrong number of values", name)
+            }
+            InterpretError::Recursion(name) => write!(f, "`{}` calls itself", name),
+            InterpretError::NoValue(instruction) => {
+                write!(f, "{} does not write a value", instruction)
+            }
+        }
+    }
+}
+
+impl std::error::Error for InterpretError {}
+
+/// How a block was left
+enum Flow {
+    Done,
+    Break,
+    Return(Vec<char>),
+}
+
+/// Everything a block jumps between: its instructions and line ends
+enum Unit<'a> {
+    Instr(&'a StackInstr),
+    LineEnd,
+}
+
+pub struct Interpreter {
+    rows: Vec<Vec<[char; CPF]>>,
+    row: usize,
+    col: usize,
+    vars: HashMap<String, Vec<char>>,
+    steps: u64,
+    max_steps: u64,
+    functions: Rc<HashMap<String, Function>>,
+    /// Functions the sheet is in
+    calls: Vec<String>,
+}
+
+impl Interpreter {
+    pub fn new(max_steps: u64) -> Self {
+        Interpreter {
+            rows: vec![vec![]],
+            row: 0,
+            col: 0,
+            vars: HashMap::new(),
+            steps: 0,
+            max_steps,
+            functions: Rc::default(),
+            calls: vec![],
+        }
+    }
+
+    /// A sheet for a call, with the inputs written on the first row
+    fn sub(&self, inputs: &[Vec<char>]) -> Interpreter {
+        let mut sub = Interpreter::new(self.max_steps.saturating_sub(self.steps));
+        sub.functions = self.functions.clone();
+        sub.calls = self.calls.clone();
+        for input in inputs {
+            sub.write(input);
+        }
+        sub
+    }
+
+    fn call_fn(&mut self, name: &str, args: &[Cell]) -> Result<Vec<char>, InterpretError> {
+        let function = self
+            .functions
+            .get(name)
+            .cloned()
+            .ok_or(InterpretError::UnknownFunction(name.to_string()))?;
+        if function.params.len() != args.len() {
+            return Err(InterpretError::ArgCount(name.to_string()));
+        }
+        if self.calls.iter().any(|call| call == name) {
+            return Err(InterpretError::Recursion(name.to_string()));
+        }
+
+        let values = args
+            .iter()
+            .map(|arg| self.read(arg))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut sub = self.sub(&values);
+        sub.calls.push(name.to_string());
+        sub.vars = function.params.into_iter().zip(values).collect();
+        sub.newline();
+        let result = sub.lines(&function.body);
+        self.steps += sub.steps;
+        result
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Writes a value in the cell of the cursor, spaces keep what was there.
+    pub fn write(&mut self, chars: &[char]) {
+        for (i, chunk) in chars.chunks(CPF).enumerate() {
+            let row = &mut self.rows[self.row];
+            let col = self.col + i;
+            if row.len() <= col {
+                row.resize(col + 1, [' '; CPF]);
+            }
+            for (cell, &c) in row[col].iter_mut().zip(chunk) {
+                if c != ' ' {
+                    *cell = c;
+                }
+            }
+        }
+        self.col += chars.len().div_ceil(CPF);
+    }
+
+    pub fn newline(&mut self) {
+        self.row += 1;
+        self.col = 0;
+        if self.rows.len() <= self.row {
+            self.rows.push(vec![]);
+        }
+    }
+
+    fn read(&self, cell: &Cell) -> Result<Vec<char>, InterpretError> {
+        match cell {
+            Cell::Rel(pos) => {
+                let row = self.row as i64 + pos.y as i64;
+                let col = self.col as i64 + pos.x as i64;
+                let cell = (row >= 0 && col >= 0)
+                    .then(|| self.rows.get(row as usize)?.get(col as usize))
+                    .flatten();
+                Ok(cell.map_or(vec![' '; CPF], |cell| cell.to_vec()))
+            }
+            Cell::Var(name) => self
+                .vars
+                .get(name)
+                .cloned()
+                .ok_or(InterpretError::UnknownVar(name.clone())),
+        }
+    }
+
+    fn num(&self, cell: &Cell) -> Result<f64, InterpretError> {
+        Ok(f64::from_chars(self.read(cell)?))
+    }
+
+    fn holds(&self, cond: &Cond) -> Result<bool, InterpretError> {
+        Ok(match cond {
+            Cond::Cmp(a, ordering, b) => compare_f64(self.num(a)?, self.num(b)?, *ordering),
+            Cond::CmpVal(a, ordering, val) => compare_f64(self.num(a)?, *val, *ordering),
+            Cond::Empty(cell) => self.read(cell)?.iter().all(|&c| c == ' '),
+            Cond::Not(cond) => !self.holds(cond)?,
+        })
+    }
+
+    fn op(&self, a: &Cell, b: &Cell, op: fn(f64, f64) -> f64) -> Result<Vec<char>, InterpretError> {
+        Ok(op(self.num(a)?, self.num(b)?).chars_ref())
+    }
+
+    /// What a value instruction writes
+    fn value(&mut self, instruction: &StackInstr) -> Result<Option<Vec<char>>, InterpretError> {
+        Ok(Some(match instruction {
+            StackInstr::Text(text) => text.to_vec(),
+            StackInstr::Write(val) => val.chars_ref(),
+            StackInstr::Copy(cell) => self.read(cell)?,
+            StackInstr::Add(a, b) => self.op(a, b, |a, b| a + b)?,
+            StackInstr::Sub(a, b) => self.op(a, b, |a, b| a - b)?,
+            StackInstr::Mod(a, b) => self.op(a, b, |a, b| a % b)?,
+            StackInstr::Mul(a, b) => self.op(a, b, |a, b| a * b)?,
+            StackInstr::Div(a, b) => self.op(a, b, |a, b| a / b)?,
+            StackInstr::Call { substack, inputs } => {
+                let inputs = inputs
+                    .iter()
+                    .map(|input| self.read(input))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut sub = self.sub(&inputs);
+                sub.newline();
+                let result = sub.lines(substack);
+                self.steps += sub.steps;
+                result?
+            }
+            StackInstr::CallFn { name, args } => self.call_fn(name, args)?,
+            _ => return Ok(None),
+        }))
+    }
+
+    fn step(&mut self) -> Result<(), InterpretError> {
+        self.steps += 1;
+        match self.steps > self.max_steps {
+            true => Err(InterpretError::StepLimit(self.max_steps)),
+            false => Ok(()),
+        }
+    }
+
+    /// Runs rows with a line end between them, and after the last one if
+    /// `end_row` is set.
+    fn block(&mut self, rows: &[Vec<StackInstr>], end_row: bool) -> Result<Flow, InterpretError> {
+        let mut units = vec![];
+        for (i, row) in rows.iter().enumerate() {
+            units.extend(row.iter().map(Unit::Instr));
+            if end_row || i + 1 < rows.len() {
+                units.push(Unit::LineEnd);
+            }
+        }
+
+        let mut pc = 0;
+        while let Some(unit) = units.get(pc) {
+            self.step()?;
+            let instruction = match unit {
+                Unit::Instr(instruction) => instruction,
+                Unit::LineEnd => {
+                    self.newline();
+                    pc += 1;
+                    continue;
+                }
+            };
+
+            let jump = match instruction {
+                StackInstr::Jump(rel) => Some(*rel),
+                StackInstr::JumpRelIf(cell, ordering, val, rel) => {
+                    compare_f64(self.num(cell)?, *val, *ordering).then_some(*rel)
+                }
+                StackInstr::JumpEmpty(cell, rel) => {
+                    self.holds(&Cond::Empty(cell.clone()))?.then_some(*rel)
+                }
+                StackInstr::JumpRelCmp(a, b, ordering, rel) => {
+                    compare_f64(self.num(a)?, self.num(b)?, *ordering).then_some(*rel)
+                }
+                _ => match self.instruction(instruction)? {
+                    Flow::Done => None,
+                    flow => return Ok(flow),
+                },
+            };
+            match jump {
+                Some(rel) => {
+                    let target = pc as i64 + rel;
+                    if target < 0 || target > units.len() as i64 {
+                        return Err(InterpretError::JumpOutOfBlock(rel));
+                    }
+                    pc = target as usize;
+                }
+                None => pc += 1,
+            }
+        }
+
+        Ok(Flow::Done)
+    }
+
+    fn repeat(
+        &mut self,
+        cond: Option<&Cond>,
+        body: &[Vec<StackInstr>],
+    ) -> Result<Flow, InterpretError> {
+        loop {
+            self.step()?;
+            if let Some(cond) = cond {
+                if !self.holds(cond)? {
+                    return Ok(Flow::Done);
+                }
+            }
+            match self.block(body, false)? {
+                Flow::Done => {}
+                Flow::Break => return Ok(Flow::Done),
+                Flow::Return(value) => return Ok(Flow::Return(value)),
+            }
+        }
+    }
+
+    fn instruction(&mut self, instruction: &StackInstr) -> Result<Flow, InterpretError> {
+        if let Some(value) = self.value(instruction)? {
+            self.write(&value);
+            return Ok(Flow::Done);
+        }
+
+        Ok(match instruction {
+            StackInstr::Set(name, instruction) => {
+                let value = self
+                    .value(instruction)?
+                    .ok_or_else(|| InterpretError::NoValue(format!("{:?}", instruction)))?;
+                self.write(&value);
+                self.vars.insert(name.clone(), value);
+                Flow::Done
+            }
+            StackInstr::Ret(cell) => Flow::Return(self.read(cell)?),
+            StackInstr::Break => Flow::Done,
+            StackInstr::If {
+                cond,
+                then,
+                otherwise,
+            } => match self.holds(cond)? {
+                true => self.block(then, false)?,
+                false => self.block(otherwise, false)?,
+            },
+            StackInstr::While { cond, body } => self.repeat(Some(cond), body)?,
+            StackInstr::Loop(body) => self.repeat(None, body)?,
+            StackInstr::BreakLoop => Flow::Break,
+            _ => unreachable!("jumps are handled by the block"),
+        })
+    }
+
+    /// Runs a program until it returns, with a line end after every line like
+    /// the compiled program.
+    pub fn run(&mut self, program: &Program) -> Result<Vec<char>, InterpretError> {
+        self.functions = Rc::new(program.functions.clone());
+        self.lines(&program.lines)
+    }
+
+    fn lines(&mut self, lines: &[Vec<StackInstr>]) -> Result<Vec<char>, InterpretError> {
+        match self.block(lines, true)? {
+            Flow::Done => Err(InterpretError::RanOff),
+            Flow::Break => Err(InterpretError::BreakOutsideLoop),
+            Flow::Return(value) => Ok(value),
+        }
+    }
+
+    /// The sheet in the same format as [`PaperVM::print`](papier::papervm::PaperVM::print).
+    pub fn print(&self) -> String {
+        let chars: Vec<(usize, usize, char)> = self
+            .rows
+            .iter()
+            .enumerate()
+            .flat_map(|(y, row)| {
+                row.iter().enumerate().flat_map(move |(col, cell)| {
+                    cell.iter()
+                        .enumerate()
+                        .map(move |(i, &c)| (col * CPF + i, y, c))
+                })
+            })
+            .filter(|&(_, _, c)| c != ' ')
+            .collect();
+        if chars.is_empty() {
+            return String::new();
+        }
+
+        let min_x = chars.iter().map(|&(x, _, _)| x).min().unwrap();
+        let max_x = chars.iter().map(|&(x, _, _)| x).max().unwrap();
+        let min_y = chars.iter().map(|&(_, y, _)| y).min().unwrap();
+        let max_y = chars.iter().map(|&(_, y, _)| y).max().unwrap();
+
+        let mut grid = vec![vec![' '; max_x - min_x + 1]; max_y - min_y + 1];
+        for (x, y, c) in chars {
+            grid[y - min_y][x - min_x] = c;
+        }
+
+        let mut result = String::new();
+        for row in grid {
+            result.extend(row);
+            result.push('\n');
+        }
+        result
+    }
+}
@@ -0,0 +1,618 @@
+//! A small imperative language on top of stacker programs.
+//!
+//! ```text
+//! input a, b;
+//! while b != 0 {
+//!     t = a % b; a = b; b = t;
+//! }
+//! return a;
+//! ```
+//!
+//! All values are numbers. Statements are assignments, `return e;`,
+//! `if c { ... } else { ... }` and `while c { ... }`, where conditions compare
+//! two expressions with `<`, `<=`, `>`, `>=`, `==` or `!=`. Expressions use
+//! `+`, `-`, `*`, `/`, `%`, parentheses and calls of functions defined as
+//! `fn name(a, b) { ... }`. The numbers written on the first row, like the
+//! `--arg`s of the CLI, are read into variables with `input a, b;` as the
+//! first statement.
+//!
+//! Variables become [`StackInstr::Set`]s, so every variable gets its own
+//! column. Variables first assigned in an `if` or `while` are set to 0 in
+//! front of it, so they can be read after it. Intermediate results are written in columns of their own as well,
+//! named `$0`, `$1`, ... Calls run on their own sheet, which starts by copying
+//! the arguments into the parameters.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::parse::ParseError;
+use crate::stacker::{Cell, CompileError, Cond, StackInstr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Num(f64),
+    Var(String),
+    Neg(Box<Expr>),
+    Bin(Box<Expr>, Op, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cmp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub left: Expr,
+    pub cmp: Cmp,
+    pub right: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    Input(Vec<String>),
+    Assign(String, Expr),
+    If(Comparison, Vec<Stmt>, Vec<Stmt>),
+    While(Comparison, Vec<Stmt>),
+    Return(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Program {
+    pub functions: Vec<Function>,
+    pub main: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Num(f64),
+    /// Operators and punctuation
+    Sym(&'static str),
+}
+
+const SYMBOLS: [&str; 18] = [
+    "<=", ">=", "==", "!=", "<", ">", "=", "+", "-", "*", "/", "%", "(", ")", "{", "}", ",", ";",
+];
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let mut tokens = vec![];
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let mut rest = line.trim_start();
+
+        while !rest.is_empty() && !rest.starts_with('#') {
+            if let Some(sym) = SYMBOLS.iter().find(|sym| rest.starts_with(**sym)) {
+                tokens.push((Token::Sym(sym), line_number));
+                rest = &rest[sym.len()..];
+            } else {
+                let len = rest
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+                    .unwrap_or(rest.len());
+                if len == 0 {
+                    return Err(ParseError {
+                        line: line_number,
+                        message: format!("unexpected `{}`", rest.chars().next().unwrap()),
+                    });
+                }
+                let word = &rest[..len];
+                let token = match word.parse() {
+                    Ok(num) => Token::Num(num),
+                    Err(_) if word.starts_with(|c: char| c.is_ascii_digit()) => {
+                        return Err(ParseError {
+                            line: line_number,
+                            message: format!("invalid number `{}`", word),
+                        })
+                    }
+                    Err(_) => Token::Ident(word.to_string()),
+                };
+                tokens.push((token, line_number));
+                rest = &rest[len..];
+            }
+            rest = rest.trim_start();
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(token, _)| token)
+    }
+
+    fn error(&self, message: String) -> ParseError {
+        let line = match self.tokens.get(self.next).or(self.tokens.last()) {
+            Some((_, line)) => *line,
+            None => 0,
+        };
+        ParseError { line, message }
+    }
+
+    fn unexpected(&self, expected: &str) -> ParseError {
+        match self.peek() {
+            Some(Token::Ident(name)) => {
+                self.error(format!("expected {}, found `{}`", expected, name))
+            }
+            Some(Token::Num(num)) => self.error(format!("expected {}, found {}", expected, num)),
+            Some(Token::Sym(sym)) => self.error(format!("expected {}, found `{}`", expected, sym)),
+            None => self.error(format!("expected {}, found end of file", expected)),
+        }
+    }
+
+    fn eat(&mut self, sym: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Sym(s)) if *s == sym) {
+            self.next += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, sym: &str) -> Result<(), ParseError> {
+        match self.eat(sym) {
+            true => Ok(()),
+            false => Err(self.unexpected(&format!("`{}`", sym))),
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Ident(name)) if name == keyword) {
+            self.next += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, ParseError> {
+        match self.peek() {
+            Some(Token::Ident(name)) => {
+                let name = name.clone();
+                self.next += 1;
+                Ok(name)
+            }
+            _ => Err(self.unexpected("name")),
+        }
+    }
+
+    /// `a, b, c` up to the closing symbol
+    fn list<T>(
+        &mut self,
+        close: &str,
+        mut item: impl FnMut(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<Vec<T>, ParseError> {
+        let mut items = vec![];
+        if self.eat(close) {
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            if self.eat(close) {
+                return Ok(items);
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn atom(&mut self) -> Result<Expr, ParseError> {
+        match self.peek() {
+            Some(&Token::Num(num)) => {
+                self.next += 1;
+                Ok(Expr::Num(num))
+            }
+            Some(Token::Ident(_)) => {
+                let name = self.ident()?;
+                if self.eat("(") {
+                    Ok(Expr::Call(name, self.list(")", Self::expr)?))
+                } else {
+                    Ok(Expr::Var(name))
+                }
+            }
+            Some(Token::Sym("(")) => {
+                self.next += 1;
+                let expr = self.expr()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(Token::Sym("-")) => {
+                self.next += 1;
+                Ok(Expr::Neg(Box::new(self.atom()?)))
+            }
+            _ => Err(self.unexpected("expression")),
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.atom()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Sym("*")) => Op::Mul,
+                Some(Token::Sym("/")) => Op::Div,
+                Some(Token::Sym("%")) => Op::Mod,
+                _ => return Ok(expr),
+            };
+            self.next += 1;
+            expr = Expr::Bin(Box::new(expr), op, Box::new(self.atom()?));
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.term()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Sym("+")) => Op::Add,
+                Some(Token::Sym("-")) => Op::Sub,
+                _ => return Ok(expr),
+            };
+            self.next += 1;
+            expr = Expr::Bin(Box::new(expr), op, Box::new(self.term()?));
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Comparison, ParseError> {
+        let left = self.expr()?;
+        let cmp = match self.peek() {
+            Some(Token::Sym("<")) => Cmp::Lt,
+            Some(Token::Sym("<=")) => Cmp::Le,
+            Some(Token::Sym(">")) => Cmp::Gt,
+            Some(Token::Sym(">=")) => Cmp::Ge,
+            Some(Token::Sym("==")) => Cmp::Eq,
+            Some(Token::Sym("!=")) => Cmp::Ne,
+            _ => return Err(self.unexpected("comparison")),
+        };
+        self.next += 1;
+        let right = self.expr()?;
+        Ok(Comparison { left, cmp, right })
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        self.expect("{")?;
+        let mut stmts = vec![];
+        while !self.eat("}") {
+            stmts.push(self.stmt()?);
+        }
+        Ok(stmts)
+    }
+
+    fn stmt(&mut self) -> Result<Stmt, ParseError> {
+        if self.keyword("input") {
+            let names = self.list(";", Self::ident)?;
+            return Ok(Stmt::Input(names));
+        }
+        if self.keyword("return") {
+            let expr = self.expr()?;
+            self.expect(";")?;
+            return Ok(Stmt::Return(expr));
+        }
+        if self.keyword("if") {
+            let cond = self.comparison()?;
+            let then = self.block()?;
+            let otherwise = match self.keyword("else") {
+                true if matches!(self.peek(), Some(Token::Ident(k)) if k == "if") => {
+                    vec![self.stmt()?]
+                }
+                true => self.block()?,
+                false => vec![],
+            };
+            return Ok(Stmt::If(cond, then, otherwise));
+        }
+        if self.keyword("while") {
+            let cond = self.comparison()?;
+            return Ok(Stmt::While(cond, self.block()?));
+        }
+
+        let name = self.ident()?;
+        self.expect("=")?;
+        let expr = self.expr()?;
+        self.expect(";")?;
+        Ok(Stmt::Assign(name, expr))
+    }
+
+    fn function(&mut self) -> Result<Function, ParseError> {
+        let name = self.ident()?;
+        self.expect("(")?;
+        let params = self.list(")", Self::ident)?;
+        let body = self.block()?;
+        Ok(Function { name, params, body })
+    }
+}
+
+/// Parses a program in the language described in the [module docs](self).
+pub fn parse_lang(source: &str) -> Result<Program, ParseError> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        next: 0,
+    };
+
+    let mut program = Program::default();
+    while parser.peek().is_some() {
+        if parser.keyword("fn") {
+            program.functions.push(parser.function()?);
+        } else {
+            program.main.push(parser.stmt()?);
+        }
+    }
+    Ok(program)
+}
+
+fn ends_with_return(stmts: &[Stmt]) -> bool {
+    match stmts.last() {
+        Some(Stmt::Return(_)) => true,
+        Some(Stmt::If(_, then, otherwise)) => ends_with_return(then) && ends_with_return(otherwise),
+        _ => false,
+    }
+}
+
+/// Variables assigned anywhere in the statements, in the order they appear.
+fn assigned(stmts: &[Stmt], names: &mut Vec<String>) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::Input(vars) => names.extend(vars.iter().cloned()),
+            Stmt::Assign(name, _) => names.push(name.clone()),
+            Stmt::If(_, then, otherwise) => {
+                assigned(then, names);
+                assigned(otherwise, names);
+            }
+            Stmt::While(_, body) => assigned(body, names),
+            Stmt::Return(_) => {}
+        }
+    }
+}
+
+struct Lower<'a> {
+    functions: HashMap<&'a str, &'a Function>,
+    /// Functions currently being lowered, calls are compiled in place so they
+    /// can not recurse
+    active: Vec<&'a str>,
+    /// Intermediate results of the current statement
+    temps: usize,
+    /// Variables assigned so far on the current sheet
+    known: Vec<String>,
+}
+
+impl<'a> Lower<'a> {
+    /// The instruction writing the value of the expression, with the rows
+    /// to evaluate its operands first.
+    fn value(
+        &mut self,
+        expr: &Expr,
+        row: &mut Vec<StackInstr>,
+    ) -> Result<StackInstr, CompileError> {
+        Ok(match expr {
+            Expr::Num(num) => StackInstr::Write(*num),
+            Expr::Var(name) => StackInstr::Copy(Cell::Var(name.clone())),
+            Expr::Neg(expr) => {
+                let zero = self.cell(&Expr::Num(0.), row)?;
+                StackInstr::Sub(zero, self.cell(expr, row)?)
+            }
+            Expr::Bin(a, op, b) => {
+                let (a, b) = (self.cell(a, row)?, self.cell(b, row)?);
+                match op {
+                    Op::Add => StackInstr::Add(a, b),
+                    Op::Sub => StackInstr::Sub(a, b),
+                    Op::Mul => StackInstr::Mul(a, b),
+                    Op::Div => StackInstr::Div(a, b),
+                    Op::Mod => StackInstr::Mod(a, b),
+                }
+            }
+            Expr::Call(name, args) => {
+                let inputs = args
+                    .iter()
+                    .map(|arg| self.cell(arg, row))
+                    .collect::<Result<_, _>>()?;
+                StackInstr::Call {
+                    substack: self.function(name, args.len())?,
+                    inputs,
+                }
+            }
+        })
+    }
+
+    /// A cell holding the value of the expression.
+    fn cell(&mut self, expr: &Expr, row: &mut Vec<StackInstr>) -> Result<Cell, CompileError> {
+        if let Expr::Var(name) = expr {
+            return Ok(Cell::Var(name.clone()));
+        }
+        let temp = format!("${}", self.temps);
+        self.temps += 1;
+        let value = self.value(expr, row)?;
+        row.push(StackInstr::Set(temp.clone(), Box::new(value)));
+        Ok(Cell::Var(temp))
+    }
+
+    fn cond(
+        &mut self,
+        comparison: &Comparison,
+        row: &mut Vec<StackInstr>,
+    ) -> Result<Cond, CompileError> {
+        let left = self.cell(&comparison.left, row)?;
+        let (ordering, negated) = match comparison.cmp {
+            Cmp::Lt => (Ordering::Less, false),
+            Cmp::Le => (Ordering::Greater, true),
+            Cmp::Gt => (Ordering::Greater, false),
+            Cmp::Ge => (Ordering::Less, true),
+            Cmp::Eq => (Ordering::Equal, false),
+            Cmp::Ne => (Ordering::Equal, true),
+        };
+        let cond = match comparison.right {
+            Expr::Num(val) => Cond::CmpVal(left, ordering, val),
+            ref right => Cond::Cmp(left, ordering, self.cell(right, row)?),
+        };
+        Ok(match negated {
+            true => Cond::Not(Box::new(cond)),
+            false => cond,
+        })
+    }
+
+    fn block(&mut self, stmts: &[Stmt]) -> Result<Vec<Vec<StackInstr>>, CompileError> {
+        stmts.iter().map(|stmt| self.stmt(stmt, false)).collect()
+    }
+
+    /// Sets the variables first assigned in the blocks to 0 in front of
+    /// them. Only variables set before a block are kept after it.
+    fn declare(&mut self, blocks: &[&[Stmt]], row: &mut Vec<StackInstr>) {
+        let mut names = vec![];
+        for block in blocks {
+            assigned(block, &mut names);
+        }
+        for name in names {
+            if !self.known.contains(&name) {
+                row.push(StackInstr::Set(
+                    name.clone(),
+                    Box::new(StackInstr::Write(0.)),
+                ));
+                self.known.push(name);
+            }
+        }
+    }
+
+    /// One row per statement
+    fn stmt(&mut self, stmt: &Stmt, first: bool) -> Result<Vec<StackInstr>, CompileError> {
+        self.temps = 0;
+        let mut row = vec![];
+        match stmt {
+            Stmt::Input(names) if first => {
+                // The row above holds the inputs, one per column like the
+                // variables
+                for name in names {
+                    let value = StackInstr::Copy((0, -1).into());
+                    row.push(StackInstr::Set(name.clone(), Box::new(value)));
+                    self.known.push(name.clone());
+                }
+            }
+            Stmt::Input(_) => {
+                return Err("`input` has to be the first statement".to_string().into());
+            }
+            Stmt::Assign(name, expr) => {
+                let value = self.value(expr, &mut row)?;
+                row.push(StackInstr::Set(name.clone(), Box::new(value)));
+                self.known.push(name.clone());
+            }
+            Stmt::Return(expr) => {
+                let cell = self.cell(expr, &mut row)?;
+                row.push(StackInstr::Ret(cell));
+            }
+            Stmt::If(comparison, then, otherwise) => {
+                self.declare(&[then, otherwise], &mut row);
+                let cond = self.cond(comparison, &mut row)?;
+                row.push(StackInstr::If {
+                    cond,
+                    then: self.block(then)?,
+                    otherwise: self.block(otherwise)?,
+                });
+            }
+            Stmt::While(comparison, body) => {
+                let mut declared = vec![];
+                self.declare(&[body], &mut declared);
+                let cond = self.cond(comparison, &mut row)?;
+                let body = self.block(body)?;
+                if row.is_empty() {
+                    row.push(StackInstr::While { cond, body });
+                } else {
+                    // The operands have to be worked out again every time
+                    row.push(StackInstr::If {
+                        cond: Cond::Not(Box::new(cond)),
+                        then: vec![vec![StackInstr::BreakLoop]],
+                        otherwise: vec![],
+                    });
+                    let mut rows = vec![row];
+                    rows.extend(body);
+                    row = vec![StackInstr::Loop(rows)];
+                }
+                declared.append(&mut row);
+                row = declared;
+            }
+        }
+        Ok(row)
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) -> Result<Vec<Vec<StackInstr>>, CompileError> {
+        stmts
+            .iter()
+            .enumerate()
+            .map(|(i, stmt)| self.stmt(stmt, i == 0))
+            .collect()
+    }
+
+    fn function(&mut self, name: &str, args: usize) -> Result<Vec<Vec<StackInstr>>, CompileError> {
+        let function = *self
+            .functions
+            .get(name)
+            .ok_or(format!("unknown function `{}`", name))?;
+        if function.params.len() != args {
+            return Err(format!(
+                "`{}` takes {} arguments, not {}",
+                name,
+                function.params.len(),
+                args
+            )
+            .into());
+        }
+        if self.active.contains(&function.name.as_str()) {
+            return Err(format!("`{}` calls itself", name).into());
+        }
+        if !ends_with_return(&function.body) {
+            return Err(format!("`{}` does not end with `return`", name).into());
+        }
+
+        let mut body = vec![Stmt::Input(function.params.clone())];
+        body.extend(function.body.iter().cloned());
+
+        let temps = self.temps;
+        let known = std::mem::take(&mut self.known);
+        self.active.push(&function.name);
+        let rows = self.stmts(&body);
+        self.active.pop();
+        self.temps = temps;
+        self.known = known;
+
+        rows.map_err(|e| format!("in `{}`: {}", name, e).into())
+    }
+}
+
+/// Lowers a program to stacker rows, to be compiled with
+/// [`compile_stacker`](crate::stacker::compile_stacker).
+pub fn lower(program: &Program) -> Result<Vec<Vec<StackInstr>>, CompileError> {
+    let mut lower = Lower {
+        functions: HashMap::new(),
+        active: vec![],
+        temps: 0,
+        known: vec![],
+    };
+    for function in &program.functions {
+        if lower.functions.insert(&function.name, function).is_some() {
+            return Err(format!("function `{}` is defined twice", function.name).into());
+        }
+    }
+
+    lower.stmts(&program.main)
+}
@@ -0,0 +1,5 @@
+pub mod check;
+pub mod interpret;
+pub mod lang;
+pub mod parse;
+pub mod stacker;
@@ -1,23 +1,109 @@
-mod stacker;
-
-use render_staal::run_program;
-use stacker::*;
-
-fn main() {
-    let mut program = sort();
-    program.insert(
-        0,
-        vec![
-            StackInstr::Write(1223.0),
-            StackInstr::Write(127.0),
-            StackInstr::Write(72.0),
-            StackInstr::Write(61.0),
-            StackInstr::Write(39.0),
-            StackInstr::Write(5.0),
-        ],
-    );
-
-    let compiled = compile_stacker(program);
-
-    run_program(compiled).unwrap();
+use std::{error::Error, fs};
+
+use clap::{value_parser, Arg, ArgAction, Command};
+use daan_compile::{
+    check::check_program,
+    lang::{lower, parse_lang},
+    parse::parse_stacker,
+    stacker::*,
+};
+use papier::{
+    listing::{listing, ListingOptions},
+    optimize::{optimize_verified, OptimizeOptions},
+    papervm::CharCell,
+};
+use render_staal::{run_program, run_program_with_source};
+
+fn cli() -> Command {
+    Command::new("daan_compile")
+        .about("Compiles a stacker program and runs it in the TUI")
+        .arg(
+            Arg::new("program").required(true).help(
+                "Stacker program, or a program in the expression language if it ends in .daan",
+            ),
+        )
+        .arg(
+            Arg::new("arg")
+                .long("arg")
+                .action(ArgAction::Append)
+                .value_parser(value_parser!(f64))
+                .help("Numbers written on a row above the program"),
+        )
+        .arg(
+            Arg::new("list")
+                .long("list")
+                .action(ArgAction::SetTrue)
+                .help("Print the compiled instructions instead of running them"),
+        )
+        .arg(
+            Arg::new("check")
+                .long("check")
+                .action(ArgAction::SetTrue)
+                .help("Check which cells hold text and which numbers instead of running"),
+        )
+        .arg(
+            Arg::new("optimize")
+                .long("optimize")
+                .action(ArgAction::SetTrue)
+                .help(
+                    "Run the peephole optimizer on the compiled program, if it writes the same \
+                     sheets",
+                ),
+        )
+}
+
+/// Steps to run a program in to check that optimizing it changes nothing
+const VERIFY_STEPS: u64 = 1_000_000;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let matches = cli().get_matches();
+
+    let path = matches.get_one::<String>("program").unwrap();
+    let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let mut program = match path.ends_with(".daan") {
+        true => Program::from(lower(&parse_lang(&source)?)?),
+        false => parse_stacker(&source)?,
+    };
+
+    let args: Vec<StackInstr> = matches
+        .get_many::<f64>("arg")
+        .unwrap_or_default()
+        .map(|&arg| StackInstr::Write(arg))
+        .collect();
+    if !args.is_empty() {
+        program.lines.insert(0, args);
+    }
+
+    if matches.get_flag("check") {
+        let errors = check_program(&program);
+        for error in &errors {
+            eprintln!("{}", error);
+        }
+        return match errors.len() {
+            0 => Ok(()),
+            count => Err(format!("{} problems found", count).into()),
+        };
+    }
+
+    let (mut compiled, map) = compile_program(program)?;
+    // Optimizing moves instructions around, so the source map no longer fits
+    let optimized = matches.get_flag("optimize");
+    if optimized {
+        compiled =
+            optimize_verified::<CharCell>(compiled, &OptimizeOptions::default(), VERIFY_STEPS)
+                .map_err(|e| format!("could not verify the optimized program: {}", e))?;
+    }
+
+    if matches.get_flag("list") {
+        print!("{}", listing(&compiled, &ListingOptions::default()));
+        return Ok(());
+    }
+
+    match optimized {
+        true => run_program(compiled),
+        false => run_program_with_source(
+            compiled,
+            Box::new(move |trace| map.get(trace).map(|loc| loc.to_string())),
+        ),
+    }
 }
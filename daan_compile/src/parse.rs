@@ -0,0 +1,664 @@
+//! Text syntax for stacker programs.
+//!
+//! Every line is one row on the paper, with the instructions of the row
+//! separated by `;`. Cells are referenced relative to the current cell as
+//! `[x,y]`, in columns of `CHARS_PER_FLOAT` characters, or by the name of a
+//! variable set with `name = <instruction>`. Functions are defined
+//! in `fn <name>(<params>)` ... `end` blocks and called with
+//! `call name([0,-1], x)`, all other lines form the main program. The values
+//! a function is called with are on the row above its first line, and can be
+//! read by the names of its parameters:
+//!
+//! ```text
+//! fn double(x)
+//!     add x x; ret [-1,0]
+//! end
+//!
+//! write 21
+//! call double([0,-1]); breakpoint
+//! ```
+//!
+//! | syntax                        | instruction            |
+//! |-------------------------------|------------------------|
+//! | `text "abc"`                  | [`StackInstr::Text`]       |
+//! | `write 12`                    | [`StackInstr::Write`]      |
+//! | `copy [x,y]`                  | [`StackInstr::Copy`]       |
+//! | `add`/`sub`/`mod [x,y] [x,y]` | [`StackInstr::Add`], ...   |
+//! | `mul`/`div [x,y] [x,y]`       | [`StackInstr::Mul`], ...   |
+//! | `jmp n`                       | [`StackInstr::Jump`]       |
+//! | `jmp_if [x,y] < 0 n`          | [`StackInstr::JumpRelIf`]  |
+//! | `jmp_empty [x,y] n`           | [`StackInstr::JumpEmpty`]  |
+//! | `jmp_cmp [x,y] [x,y] > n`     | [`StackInstr::JumpRelCmp`] |
+//! | `ret [x,y]`                   | [`StackInstr::Ret`]        |
+//! | `breakpoint`                  | [`StackInstr::Break`]      |
+//! | `call name([x,y], ...)`       | [`StackInstr::CallFn`]     |
+//! | `x = add x [0,-1]`            | [`StackInstr::Set`]        |
+//! | `if c { ... } else { ... }`   | [`StackInstr::If`]         |
+//! | `while c { ... }`             | [`StackInstr::While`]      |
+//! | `loop { ... }`                | [`StackInstr::Loop`]       |
+//! | `break`                       | [`StackInstr::BreakLoop`]  |
+//!
+//! Conditions are `[x,y] < [x,y]`, `[x,y] = 3`, `empty [x,y]` or `not c`.
+//! Blocks in braces stay on the row they are written on. Leaving out the
+//! braces puts the block on the following lines, up to `else` or `end`, one
+//! row per line. The row the block is on continues after `end;`:
+//!
+//! ```text
+//! loop
+//!     while not empty [1,-1] { copy [0,-1] }; copy [0,-1]
+//! end
+//! ```
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+
+use crate::stacker::{text, Cell, Cond, Function, Program, StackInstr};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(f64),
+    Cell(i32, i32),
+    Ord(Ordering),
+    Semi,
+    Open,
+    Close,
+    Comma,
+    BlockOpen,
+    BlockClose,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(name) => write!(f, "`{}`", name),
+            Token::Str(string) => write!(f, "{:?}", string),
+            Token::Num(num) => write!(f, "{}", num),
+            Token::Cell(x, y) => write!(f, "[{},{}]", x, y),
+            Token::Ord(ord) => write!(f, "{:?}", ord),
+            Token::Semi => write!(f, "`;`"),
+            Token::Open => write!(f, "`(`"),
+            Token::Close => write!(f, "`)`"),
+            Token::Comma => write!(f, "`,`"),
+            Token::BlockOpen => write!(f, "`{{`"),
+            Token::BlockClose => write!(f, "`}}`"),
+        }
+    }
+}
+
+fn tokenize(line: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = line.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            '#' => break,
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            ';' | '(' | ')' | ',' | '<' | '=' | '>' | '{' | '}' => {
+                chars.next();
+                tokens.push(match c {
+                    ';' => Token::Semi,
+                    '(' => Token::Open,
+                    ')' => Token::Close,
+                    ',' => Token::Comma,
+                    '{' => Token::BlockOpen,
+                    '}' => Token::BlockClose,
+                    '<' => Token::Ord(Ordering::Less),
+                    '=' => Token::Ord(Ordering::Equal),
+                    _ => Token::Ord(Ordering::Greater),
+                });
+            }
+            '"' => {
+                chars.next();
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('"') => string.push('"'),
+                            Some('\\') => string.push('\\'),
+                            other => return Err(format!("unknown escape `\\{:?}`", other)),
+                        },
+                        Some(c) => string.push(c),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+                tokens.push(Token::Str(string));
+            }
+            '[' => {
+                chars.next();
+                let inner: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                let cell = match inner.split(',').map(str::trim).collect::<Vec<_>>()[..] {
+                    [x, y] => x.parse().ok().zip(y.parse().ok()),
+                    _ => None,
+                };
+                let (x, y) = cell.ok_or(format!("invalid cell `[{}]`", inner))?;
+                tokens.push(Token::Cell(x, y));
+            }
+            _ => {
+                let mut token = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "#;(),[]{}\"<=>".contains(c) {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+                if token.is_empty() {
+                    return Err(format!("unexpected `{}`", c));
+                }
+                match token.parse() {
+                    Ok(num) => tokens.push(Token::Num(num)),
+                    Err(_) => tokens.push(Token::Ident(token)),
+                }
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Line {
+    number: usize,
+    tokens: Vec<Token>,
+}
+
+impl Line {
+    fn keyword(&self) -> Option<&str> {
+        match &self.tokens[..] {
+            [Token::Ident(_), Token::Ord(Ordering::Equal), ..] => None,
+            [Token::Ident(keyword), ..] => Some(keyword),
+            _ => None,
+        }
+    }
+
+    /// Whether the last instruction of the line starts a block on the next
+    /// lines.
+    fn opens_block(&self) -> bool {
+        let last = self.tokens.rsplit(|t| *t == Token::Semi).next().unwrap();
+        let keyword = match last.first() {
+            Some(Token::Ident(keyword)) => keyword.as_str(),
+            _ => return false,
+        };
+        ["if", "while", "loop"].contains(&keyword) && !last.contains(&Token::BlockOpen)
+    }
+}
+
+struct Tokens<'a> {
+    line: usize,
+    tokens: std::iter::Peekable<std::slice::Iter<'a, Token>>,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(line: &'a Line) -> Self {
+        Tokens {
+            line: line.number,
+            tokens: line.tokens.iter().peekable(),
+        }
+    }
+
+    fn error(&self, message: String) -> ParseError {
+        ParseError {
+            line: self.line,
+            message,
+        }
+    }
+
+    fn unexpected(&self, token: &Token, expected: &str) -> ParseError {
+        self.error(format!("expected {}, found {}", expected, token))
+    }
+
+    fn peek(&mut self) -> Option<&'a Token> {
+        self.tokens.peek().copied()
+    }
+
+    fn next(&mut self, expected: &str) -> Result<&'a Token, ParseError> {
+        match self.tokens.next() {
+            Some(token) => Ok(token),
+            None => Err(self.error(format!("expected {}, found end of line", expected))),
+        }
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), ParseError> {
+        match self.next(&expected.to_string())? {
+            t if *t == expected => Ok(()),
+            t => Err(self.unexpected(t, &expected.to_string())),
+        }
+    }
+
+    fn cell(&mut self) -> Result<Cell, ParseError> {
+        match self.next("cell or variable")? {
+            &Token::Cell(x, y) => Ok(Cell::from((x, y))),
+            Token::Ident(name) => Ok(Cell::from(name.as_str())),
+            t => Err(self.unexpected(t, "cell or variable")),
+        }
+    }
+
+    fn int(&mut self) -> Result<i64, ParseError> {
+        match self.next("integer")? {
+            &Token::Num(num) if num.fract() == 0. => Ok(num as i64),
+            t => Err(self.unexpected(t, "integer")),
+        }
+    }
+
+    fn num(&mut self) -> Result<f64, ParseError> {
+        match self.next("number")? {
+            &Token::Num(num) => Ok(num),
+            t => Err(self.unexpected(t, "number")),
+        }
+    }
+
+    fn ordering(&mut self) -> Result<Ordering, ParseError> {
+        match self.next("`<`, `=` or `>`")? {
+            &Token::Ord(ord) => Ok(ord),
+            t => Err(self.unexpected(t, "`<`, `=` or `>`")),
+        }
+    }
+
+    fn string(&mut self) -> Result<&'a str, ParseError> {
+        match self.next("string")? {
+            Token::Str(string) => Ok(string),
+            t => Err(self.unexpected(t, "string")),
+        }
+    }
+
+    fn ident(&mut self) -> Result<&'a str, ParseError> {
+        match self.next("name")? {
+            Token::Ident(name) => Ok(name),
+            t => Err(self.unexpected(t, "name")),
+        }
+    }
+
+    /// `([x,y], [x,y], ...)`
+    fn cells(&mut self) -> Result<Vec<Cell>, ParseError> {
+        self.expect(Token::Open)?;
+        let mut cells = vec![];
+        if self.peek() == Some(&Token::Close) {
+            self.tokens.next();
+            return Ok(cells);
+        }
+        loop {
+            cells.push(self.cell()?);
+            match self.next("`,` or `)`")? {
+                Token::Comma => {}
+                Token::Close => return Ok(cells),
+                t => return Err(self.unexpected(t, "`,` or `)`")),
+            }
+        }
+    }
+
+    /// Names of the parameters of a function, if it has any.
+    fn params(&mut self) -> Result<Vec<String>, ParseError> {
+        let mut params = vec![];
+        if self.peek().is_none() {
+            return Ok(params);
+        }
+        self.expect(Token::Open)?;
+        if self.peek() == Some(&Token::Close) {
+            self.tokens.next();
+            return Ok(params);
+        }
+        loop {
+            params.push(self.ident()?.to_string());
+            match self.next("`,` or `)`")? {
+                Token::Comma => {}
+                Token::Close => return Ok(params),
+                t => return Err(self.unexpected(t, "`,` or `)`")),
+            }
+        }
+    }
+
+    fn cond(&mut self) -> Result<Cond, ParseError> {
+        match self.peek() {
+            Some(Token::Ident(k)) if k == "not" => {
+                self.tokens.next();
+                Ok(Cond::Not(Box::new(self.cond()?)))
+            }
+            Some(Token::Ident(k)) if k == "empty" => {
+                self.tokens.next();
+                Ok(Cond::Empty(self.cell()?))
+            }
+            _ => {
+                let a = self.cell()?;
+                let ordering = self.ordering()?;
+                match self.peek() {
+                    Some(&Token::Num(val)) => {
+                        self.tokens.next();
+                        Ok(Cond::CmpVal(a, ordering, val))
+                    }
+                    _ => Ok(Cond::Cmp(a, ordering, self.cell()?)),
+                }
+            }
+        }
+    }
+}
+
+/// Index of the `end` of the function starting at `lines[0]`.
+fn function_end(lines: &[&Line]) -> Option<usize> {
+    let mut depth = 0;
+    for (i, line) in lines.iter().enumerate() {
+        match line.keyword() {
+            Some("end") => depth -= 1,
+            Some("else") => continue,
+            _ => {}
+        }
+        if depth == 0 && i > 0 {
+            return Some(i);
+        }
+        if i == 0 || line.opens_block() {
+            depth += 1;
+        }
+    }
+    None
+}
+
+/// Body of a block, either `{ ... }` or on the next lines
+enum Body {
+    Inline(Vec<StackInstr>),
+    Lines,
+}
+
+struct Parser<'a> {
+    /// Parameters and lines of the functions
+    functions: HashMap<&'a str, (Vec<String>, Vec<&'a Line>)>,
+    lines: Vec<&'a Line>,
+    /// Next line to be parsed
+    next: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn function(&mut self, name: &'a str) -> Result<Function, ParseError> {
+        let (params, lines) = self.functions[name].clone();
+        self.lines = lines;
+        self.next = 0;
+        Ok(Function {
+            params,
+            body: self.rows(None)?,
+        })
+    }
+
+    /// Rows up to a line starting with one of the keywords, which is left as
+    /// the next line.
+    fn rows(&mut self, until: Option<&[&str]>) -> Result<Vec<Vec<StackInstr>>, ParseError> {
+        let mut rows = vec![];
+        while let Some(line) = self.lines.get(self.next).copied() {
+            match (line.keyword(), until) {
+                (Some(k), Some(until)) if until.contains(&k) => return Ok(rows),
+                (Some(k @ ("end" | "else")), _) => {
+                    return Err(ParseError {
+                        line: line.number,
+                        message: format!("`{}` without a block", k),
+                    })
+                }
+                _ => {}
+            }
+            self.next += 1;
+            rows.push(self.row(&mut Tokens::new(line))?);
+        }
+
+        match until {
+            Some(until) => Err(ParseError {
+                line: self.lines.last().map_or(0, |l| l.number),
+                message: format!("expected `{}`, found end of file", until.join("` or `")),
+            }),
+            None => Ok(rows),
+        }
+    }
+
+    /// Instructions up to the end of the line, going on after the `end` of
+    /// blocks on the next lines.
+    fn row(&mut self, tokens: &mut Tokens<'a>) -> Result<Vec<StackInstr>, ParseError> {
+        let mut row = vec![];
+        loop {
+            row.push(self.instruction(tokens)?);
+            match tokens.tokens.next() {
+                None => return Ok(row),
+                Some(Token::Semi) => {}
+                Some(t) => return Err(tokens.unexpected(t, "`;` or end of line")),
+            }
+        }
+    }
+
+    /// Instructions up to the closing `}`.
+    fn inline(&mut self, tokens: &mut Tokens<'a>) -> Result<Vec<StackInstr>, ParseError> {
+        let mut row = vec![];
+        if tokens.peek() == Some(&Token::BlockClose) {
+            tokens.tokens.next();
+            return Ok(row);
+        }
+        loop {
+            row.push(self.instruction(tokens)?);
+            match tokens.next("`;` or `}`")? {
+                Token::Semi => {}
+                Token::BlockClose => return Ok(row),
+                t => return Err(tokens.unexpected(t, "`;` or `}`")),
+            }
+        }
+    }
+
+    fn body(&mut self, tokens: &mut Tokens<'a>) -> Result<Body, ParseError> {
+        match tokens.peek() {
+            Some(Token::BlockOpen) => {
+                tokens.tokens.next();
+                Ok(Body::Inline(self.inline(tokens)?))
+            }
+            None => Ok(Body::Lines),
+            Some(t) => Err(tokens.unexpected(t, "`{` or end of line")),
+        }
+    }
+
+    /// Rows of a block on the next lines. Each line ends its row, so the
+    /// block ends with an empty row.
+    fn lines_until(&mut self, until: &[&str]) -> Result<Vec<Vec<StackInstr>>, ParseError> {
+        let mut rows = self.rows(Some(until))?;
+        rows.push(vec![]);
+        Ok(rows)
+    }
+
+    /// Continues with what follows the `else` or `end` closing a block.
+    fn after_block(&mut self, tokens: &mut Tokens<'a>) {
+        let line = self.lines[self.next];
+        self.next += 1;
+        *tokens = Tokens::new(line);
+        tokens.tokens.next();
+    }
+
+    fn block(&mut self, tokens: &mut Tokens<'a>) -> Result<Vec<Vec<StackInstr>>, ParseError> {
+        Ok(match self.body(tokens)? {
+            Body::Inline(row) => vec![row],
+            Body::Lines => {
+                let rows = self.lines_until(&["end"])?;
+                self.after_block(tokens);
+                rows
+            }
+        })
+    }
+
+    fn if_else(&mut self, tokens: &mut Tokens<'a>) -> Result<StackInstr, ParseError> {
+        let cond = tokens.cond()?;
+        let (then, otherwise) = match self.body(tokens)? {
+            Body::Inline(then) => {
+                let otherwise = match tokens.peek() {
+                    Some(Token::Ident(k)) if k == "else" => {
+                        tokens.tokens.next();
+                        tokens.expect(Token::BlockOpen)?;
+                        vec![self.inline(tokens)?]
+                    }
+                    _ => vec![],
+                };
+                (vec![then], otherwise)
+            }
+            Body::Lines => {
+                let then = self.lines_until(&["else", "end"])?;
+                let otherwise = match self.lines[self.next].keyword() {
+                    Some("else") => {
+                        self.after_block(tokens);
+                        if let Some(t) = tokens.peek() {
+                            return Err(tokens.unexpected(t, "end of line"));
+                        }
+                        self.lines_until(&["end"])?
+                    }
+                    _ => vec![],
+                };
+                self.after_block(tokens);
+                (then, otherwise)
+            }
+        };
+
+        Ok(StackInstr::If {
+            cond,
+            then,
+            otherwise,
+        })
+    }
+
+    fn instruction(&mut self, tokens: &mut Tokens<'a>) -> Result<StackInstr, ParseError> {
+        let name = tokens.ident()?;
+
+        if tokens.peek() == Some(&Token::Ord(Ordering::Equal)) {
+            tokens.tokens.next();
+            let value = self.instruction(tokens)?;
+            return Ok(StackInstr::Set(name.to_string(), Box::new(value)));
+        }
+
+        Ok(match name {
+            "text" => text(tokens.string()?),
+            "write" => StackInstr::Write(tokens.num()?),
+            "copy" => StackInstr::Copy(tokens.cell()?),
+            "add" => StackInstr::Add(tokens.cell()?, tokens.cell()?),
+            "sub" => StackInstr::Sub(tokens.cell()?, tokens.cell()?),
+            "mod" => StackInstr::Mod(tokens.cell()?, tokens.cell()?),
+            "mul" => StackInstr::Mul(tokens.cell()?, tokens.cell()?),
+            "div" => StackInstr::Div(tokens.cell()?, tokens.cell()?),
+            "jmp" => StackInstr::Jump(tokens.int()?),
+            "jmp_if" => StackInstr::JumpRelIf(
+                tokens.cell()?,
+                tokens.ordering()?,
+                tokens.num()?,
+                tokens.int()?,
+            ),
+            "jmp_empty" => StackInstr::JumpEmpty(tokens.cell()?, tokens.int()?),
+            "jmp_cmp" => StackInstr::JumpRelCmp(
+                tokens.cell()?,
+                tokens.cell()?,
+                tokens.ordering()?,
+                tokens.int()?,
+            ),
+            "ret" => StackInstr::Ret(tokens.cell()?),
+            "breakpoint" => StackInstr::Break,
+            "break" => StackInstr::BreakLoop,
+            "call" => {
+                let name = tokens.ident()?;
+                if !self.functions.contains_key(name) {
+                    return Err(tokens.error(format!("unknown function `{}`", name)));
+                }
+                StackInstr::CallFn {
+                    name: name.to_string(),
+                    args: tokens.cells()?,
+                }
+            }
+            "if" => self.if_else(tokens)?,
+            "while" => {
+                let cond = tokens.cond()?;
+                StackInstr::While {
+                    cond,
+                    body: self.block(tokens)?,
+                }
+            }
+            "loop" => StackInstr::Loop(self.block(tokens)?),
+            _ => return Err(tokens.error(format!("unknown instruction `{}`", name))),
+        })
+    }
+}
+
+/// Parses a stacker program in the syntax described in the [module docs](self).
+pub fn parse_stacker(source: &str) -> Result<Program, ParseError> {
+    let mut lines = vec![];
+    for (i, line) in source.lines().enumerate() {
+        let tokens = tokenize(line).map_err(|message| ParseError {
+            line: i + 1,
+            message,
+        })?;
+        if !tokens.is_empty() {
+            lines.push(Line {
+                number: i + 1,
+                tokens,
+            });
+        }
+    }
+    let lines: Vec<&Line> = lines.iter().collect();
+
+    let mut main = vec![];
+    let mut functions = HashMap::new();
+    let mut rest = &lines[..];
+    while let Some((line, tail)) = rest.split_first() {
+        match &line.tokens[..] {
+            [Token::Ident(keyword), Token::Ident(name), ..] if keyword == "fn" => {
+                let mut tokens = Tokens::new(line);
+                tokens.tokens.nth(1);
+                let params = tokens.params()?;
+                if let Some(t) = tokens.peek() {
+                    return Err(tokens.unexpected(t, "end of line"));
+                }
+                let end = function_end(rest).ok_or(ParseError {
+                    line: line.number,
+                    message: format!("function `{}` has no `end`", name),
+                })?;
+                if functions
+                    .insert(name.as_str(), (params, tail[..end - 1].to_vec()))
+                    .is_some()
+                {
+                    return Err(ParseError {
+                        line: line.number,
+                        message: format!("function `{}` is defined twice", name),
+                    });
+                }
+                rest = &rest[end + 1..];
+            }
+            _ => {
+                main.push(*line);
+                rest = tail;
+            }
+        }
+    }
+
+    let mut parser = Parser {
+        functions,
+        lines: main,
+        next: 0,
+    };
+    let lines = parser.rows(None)?;
+
+    let mut names: Vec<&str> = parser.functions.keys().copied().collect();
+    names.sort();
+    let functions = names
+        .into_iter()
+        .map(|name| {
+            let function = parser.function(name).map_err(|e| ParseError {
+                line: e.line,
+                message: format!("in `{}`: {}", name, e.message),
+            })?;
+            Ok((name.to_string(), function))
+        })
+        .collect::<Result<_, ParseError>>()?;
+
+    Ok(Program { functions, lines })
+}
@@ -1,18 +1,22 @@
+use papier::listing::{jump_offset, with_jump};
 use papier::papervm::instructions::*;
-use papier::papervm::CharCell;
 use papier::papervm::Instruction;
 use papier::papervm::IntoChars;
-use papier::papervm::PaperVM;
 use papier::papervm::Word;
+use std::cell::RefCell;
 use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::rc::Rc;
 
 use papier::papervm::CHARS_PER_FLOAT as CPF;
 
 static CPFI: i32 = CPF as i32;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Pos {
-    x: i32,
-    y: i32,
+    pub(crate) x: i32,
+    pub(crate) y: i32,
 }
 
 impl From<(i32, i32)> for Pos {
@@ -25,18 +29,17 @@ impl From<(i32, i32)> for Pos {
 }
 
 impl Pos {
-    fn to_word(&self) -> Word {
+    fn to_word(self) -> Word {
         Word::from((self.x * CPFI, self.y, CPF))
     }
 }
 
-fn text(value: &str) -> StackInstr {
+pub fn text(value: &str) -> StackInstr {
     let mut result = [' '; CPF];
 
     let chars = value.chars_ref();
-    for i in 0..CPF.min(value.len()) {
-        result[i] = chars[i];
-    }
+    let len = CPF.min(chars.len());
+    result[..len].copy_from_slice(&chars[..len]);
 
     StackInstr::Text(result)
 }
@@ -44,9 +47,8 @@ fn text(value: &str) -> StackInstr {
 fn textbox(value: &str) -> String {
     let mut result = [' '; CPF];
     let chars = value.chars_ref();
-    for i in 0..CPF.min(value.len()) {
-        result[i] = chars[i];
-    }
+    let len = CPF.min(chars.len());
+    result[..len].copy_from_slice(&chars[..len]);
 
     let mut res = String::new();
 
@@ -54,104 +56,788 @@ fn textbox(value: &str) -> String {
     res
 }
 
+/// A cell read by an instruction, either relative to the cell the cursor is
+/// on or the latest value of a variable.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell {
+    Rel(Pos),
+    Var(String),
+}
+
+impl From<Pos> for Cell {
+    fn from(value: Pos) -> Self {
+        Cell::Rel(value)
+    }
+}
+
+impl From<(i32, i32)> for Cell {
+    fn from(value: (i32, i32)) -> Self {
+        Cell::Rel(value.into())
+    }
+}
+
+impl From<&str> for Cell {
+    fn from(value: &str) -> Self {
+        Cell::Var(value.to_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum StackInstr {
     Text([char; CPF]),
     Write(f64),
-    Copy(Pos),
-    Add(Pos, Pos),
-    Sub(Pos, Pos),
-    Mod(Pos, Pos),
+    Copy(Cell),
+    Add(Cell, Cell),
+    Sub(Cell, Cell),
+    Mod(Cell, Cell),
+    Mul(Cell, Cell),
+    Div(Cell, Cell),
     Jump(i64),
-    JumpRelIf(Pos, Ordering, f64, i64),
-    JumpEmpty(Pos, i64),
-    JumpRelCmp(Pos, Pos, Ordering, i64),
-    Ret(Pos),
+    JumpRelIf(Cell, Ordering, f64, i64),
+    JumpEmpty(Cell, i64),
+    JumpRelCmp(Cell, Cell, Ordering, i64),
+    Ret(Cell),
+    /// A breakpoint, [`Instruction::BreakPoint`] on the paper
     Break,
 
     Call {
         substack: Vec<Vec<StackInstr>>,
-        inputs: Vec<Pos>,
+        inputs: Vec<Cell>,
+    },
+    /// Calls a function of the [`Program`] by name
+    CallFn {
+        name: String,
+        args: Vec<Cell>,
     },
+
+    /// Writes the value of the instruction in the column of the variable
+    Set(String, Box<StackInstr>),
+
+    If {
+        cond: Cond,
+        then: Vec<Vec<StackInstr>>,
+        otherwise: Vec<Vec<StackInstr>>,
+    },
+    While {
+        cond: Cond,
+        body: Vec<Vec<StackInstr>>,
+    },
+    Loop(Vec<Vec<StackInstr>>),
+    /// Leaves the innermost `While` or `Loop`
+    BreakLoop,
+}
+
+/// Shows the instruction in the syntax of [`parse`](crate::parse), blocks
+/// only with their header.
+impl Display for StackInstr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StackInstr::Text(text) => {
+                let text: String = text.iter().collect();
+                write!(f, "text {:?}", text.trim_end())
+            }
+            StackInstr::Write(val) => write!(f, "write {}", val),
+            StackInstr::Copy(cell) => write!(f, "copy {}", cell),
+            StackInstr::Add(a, b) => write!(f, "add {} {}", a, b),
+            StackInstr::Sub(a, b) => write!(f, "sub {} {}", a, b),
+            StackInstr::Mod(a, b) => write!(f, "mod {} {}", a, b),
+            StackInstr::Mul(a, b) => write!(f, "mul {} {}", a, b),
+            StackInstr::Div(a, b) => write!(f, "div {} {}", a, b),
+            StackInstr::Jump(rel) => write!(f, "jmp {}", rel),
+            StackInstr::JumpRelIf(cell, ordering, val, rel) => {
+                write!(f, "jmp_if {} {} {} {}", cell, symbol(*ordering), val, rel)
+            }
+            StackInstr::JumpEmpty(cell, rel) => write!(f, "jmp_empty {} {}", cell, rel),
+            StackInstr::JumpRelCmp(a, b, ordering, rel) => {
+                write!(f, "jmp_cmp {} {} {} {}", a, b, symbol(*ordering), rel)
+            }
+            StackInstr::Ret(cell) => write!(f, "ret {}", cell),
+            StackInstr::Break => write!(f, "breakpoint"),
+            StackInstr::Call { inputs, .. } => {
+                let inputs: Vec<String> = inputs.iter().map(|cell| cell.to_string()).collect();
+                write!(f, "call({})", inputs.join(", "))
+            }
+            StackInstr::CallFn { name, args } => {
+                let args: Vec<String> = args.iter().map(|cell| cell.to_string()).collect();
+                write!(f, "call {}({})", name, args.join(", "))
+            }
+            StackInstr::Set(name, value) => write!(f, "{} = {}", name, value),
+            StackInstr::If { cond, .. } => write!(f, "if {}", cond),
+            StackInstr::While { cond, .. } => write!(f, "while {}", cond),
+            StackInstr::Loop(_) => write!(f, "loop"),
+            StackInstr::BreakLoop => write!(f, "break"),
+        }
+    }
+}
+
+fn symbol(ordering: Ordering) -> &'static str {
+    match ordering {
+        Ordering::Less => "<",
+        Ordering::Equal => "=",
+        Ordering::Greater => ">",
+    }
+}
+
+impl Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cell::Rel(pos) => write!(f, "[{},{}]", pos.x, pos.y),
+            Cell::Var(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// A function called with [`StackInstr::CallFn`]. The values it is called
+/// with are on the first row of its sheet, where it reads them by the names
+/// of the parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub params: Vec<String>,
+    pub body: Vec<Vec<StackInstr>>,
+}
+
+/// The lines of the main program and the functions they call.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Program {
+    pub functions: HashMap<String, Function>,
+    pub lines: Vec<Vec<StackInstr>>,
+}
+
+impl From<Vec<Vec<StackInstr>>> for Program {
+    fn from(lines: Vec<Vec<StackInstr>>) -> Self {
+        Program {
+            functions: HashMap::new(),
+            lines,
+        }
+    }
+}
+
+/// Condition of an `If` or `While`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cond {
+    Cmp(Cell, Ordering, Cell),
+    CmpVal(Cell, Ordering, f64),
+    Empty(Cell),
+    Not(Box<Cond>),
+}
+
+impl Display for Cond {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cond::Cmp(a, ordering, b) => write!(f, "{} {} {}", a, symbol(*ordering), b),
+            Cond::CmpVal(cell, ordering, val) => {
+                write!(f, "{} {} {}", cell, symbol(*ordering), val)
+            }
+            Cond::Empty(cell) => write!(f, "empty {}", cell),
+            Cond::Not(cond) => write!(f, "not {}", cond),
+        }
+    }
+}
+
+/// The stacker instruction a compiled instruction was emitted for.
+///
+/// `line` and `column` index the lines given to [`compile_stacker`], so an
+/// instruction inside a block points at the instruction the block is part
+/// of, while `instruction` is the innermost one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLoc {
+    pub line: usize,
+    pub column: usize,
+    pub instruction: String,
+}
+
+impl Display for SourceLoc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{} {}", self.line, self.column, self.instruction)
+    }
+}
+
+/// Where every instruction of a compiled program came from.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceMap {
+    /// One entry per compiled instruction
+    pub locs: Vec<Option<SourceLoc>>,
+    /// Maps of the substacks of the calls, by the index of the `Call`
+    pub calls: HashMap<usize, SourceMap>,
+}
+
+impl SourceMap {
+    /// Looks up an instruction by the indices of the calls it is in followed
+    /// by its own index, like [`SimStepState::trace`](papier::papervm::SimStepState::trace).
+    pub fn get(&self, trace: &[usize]) -> Option<&SourceLoc> {
+        match trace {
+            [] => None,
+            [index] => self.locs.get(*index)?.as_ref(),
+            [call, rest @ ..] => self.calls.get(call)?.get(rest),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileError {
+    pub message: String,
+}
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+impl From<String> for CompileError {
+    fn from(message: String) -> Self {
+        CompileError { message }
+    }
+}
+
+/// Keeps track of where the cursor is, so variables can be turned into
+/// positions relative to it.
+///
+/// Every variable gets its own column, in the order they are first set. The
+/// cursor is followed through the program in the order it is written, jumps
+/// are expected to leave it on the same cell they found it, like the loops
+/// in [`sort`] do.
+///
+/// Blocks can run any number of times, so when variables are set before a
+/// block, every way through it ends by copying them onto a fresh row. Each
+/// iteration then finds them on the row above, wherever the previous one
+/// left the cursor. Variables first set inside a block are only known there.
+/// Without variables to copy, a block can leave the cursor in more than one
+/// place, so the next variable is set on a fresh row.
+#[derive(Debug, Clone, Default)]
+struct Layout {
+    columns: HashMap<String, i32>,
+    /// Row holding the latest value of each variable
+    latest: HashMap<String, i32>,
+    row: i32,
+    col: i32,
+    /// The cursor is on an unknown column of the row after a block
+    adrift: bool,
+}
+
+impl Layout {
+    fn word(&self, cell: &Cell) -> Result<Word, CompileError> {
+        match cell {
+            Cell::Rel(pos) => Ok(pos.to_word()),
+            Cell::Var(name) => {
+                let row = self
+                    .latest
+                    .get(name)
+                    .ok_or(format!("variable `{}` is read before it is set", name))?;
+                let pos = Pos {
+                    x: self.columns[name] - self.col,
+                    y: row - self.row,
+                };
+                Ok(pos.to_word())
+            }
+        }
+    }
+
+    fn words(&self, cells: &[Cell]) -> Result<Vec<Word>, CompileError> {
+        cells.iter().map(|cell| self.word(cell)).collect()
+    }
+
+    /// Variables with a value, from left to right
+    fn live(&self) -> Vec<String> {
+        let mut vars: Vec<String> = self.latest.keys().cloned().collect();
+        vars.sort_by_key(|var| self.columns[var]);
+        vars
+    }
+
+    /// Goes back to the state at the start of a block, keeping the columns
+    /// that were handed out in it.
+    fn restore(&mut self, entry: &Layout) {
+        self.latest = entry.latest.clone();
+        self.row = entry.row;
+        self.col = entry.col;
+        self.adrift = entry.adrift;
+    }
+}
+
+/// Functions of a program, compiled the first time they are called.
+#[derive(Debug, Default)]
+struct Library {
+    functions: HashMap<String, Function>,
+    compiled: HashMap<String, (Vec<Instruction>, SourceMap)>,
+    /// Functions being compiled, to catch recursive calls
+    active: Vec<String>,
 }
 
-pub fn compile_stacker(lines: Vec<Vec<StackInstr>>) -> Vec<Instruction> {
-    let mut stack = Vec::new();
-    for line in lines {
-        for (i, instruction) in line.into_iter().enumerate() {
-            let inst = match instruction {
-                StackInstr::Text(text) => write(text.to_vec()),
-                StackInstr::Write(val) => write(val),
-                StackInstr::Copy(pos) => Instruction::Copy(pos.to_word()),
-                StackInstr::Add(pos1, pos2) => Instruction::Add(pos1.to_word(), pos1.to_word()),
-                StackInstr::Sub(pos1, pos2) => Instruction::Sub(pos1.to_word(), pos2.to_word()),
-                StackInstr::Mod(pos1, pos2) => Instruction::Mod(pos1.to_word(), pos2.to_word()),
-                StackInstr::Call { substack, inputs } => {
-                    let mut subcalls = vec![write("\n")];
-                    subcalls.extend(compile_stacker(substack));
-
-                    Instruction::Call(subcalls, inputs.into_iter().map(|x| x.to_word()).collect())
-                }
-                StackInstr::Jump(jump) => Instruction::Jump(jump),
-                StackInstr::JumpRelIf(pos, ordering, val, dest) => {
-                    Instruction::JumpRelIf(pos.to_word(), ordering, val, dest)
-                }
-                StackInstr::JumpRelCmp(pos1, pos2, ordering, dest) => {
-                    Instruction::JumpRelCmp(pos1.to_word(), pos2.to_word(), ordering, dest)
-                }
-                StackInstr::JumpEmpty(pos, dest) => {
-                    Instruction::JumpRelIfStr(pos.to_word(), textbox(""), dest)
-                }
-                StackInstr::Ret(pos) => Instruction::Circle(pos.to_word()),
-                StackInstr::Break => Instruction::BreakPoint,
+/// What is on the sheet before the first line of a program
+enum Header {
+    Nothing,
+    /// Inputs of a `Call`
+    Inputs(Option<SourceLoc>),
+    /// Values of the parameters of a function
+    Params(String, Vec<String>),
+}
+
+/// Variables to copy at the end of a loop and jumps to its end.
+struct LoopExit {
+    vars: Vec<String>,
+    breaks: Vec<usize>,
+}
+
+#[derive(Default)]
+struct Compiler {
+    out: Vec<Instruction>,
+    /// Index in `out` of every stacker instruction and line end, which is what
+    /// the stacker jumps count
+    starts: Vec<usize>,
+    /// Jumps in `out` with the stacker index they jump from
+    jumps: Vec<(usize, usize)>,
+    layout: Layout,
+    loops: Vec<LoopExit>,
+    /// Stacker instruction being compiled
+    loc: Option<SourceLoc>,
+    map: SourceMap,
+    library: Rc<RefCell<Library>>,
+}
+
+impl Compiler {
+    fn emit(&mut self, instruction: Instruction) {
+        self.out.push(instruction);
+        self.map.locs.push(self.loc.clone());
+    }
+
+    fn newline(&mut self) {
+        self.emit(write("\n"));
+        self.layout.row += 1;
+        self.layout.col = 0;
+        self.layout.adrift = false;
+    }
+
+    /// Moves the cursor to the given column, on the next row if it has
+    /// already passed it.
+    fn goto_column(&mut self, col: i32) {
+        if col < self.layout.col || self.layout.adrift {
+            self.newline();
+        }
+        if col > self.layout.col {
+            let skip = (col - self.layout.col) as usize * CPF;
+            self.emit(write(" ".repeat(skip)));
+            self.layout.col = col;
+        }
+    }
+
+    fn jump(&mut self, instruction: Instruction) {
+        self.jumps.push((self.out.len(), self.starts.len() - 1));
+        self.emit(instruction);
+    }
+
+    /// Emits a jump to be pointed somewhere with [`Compiler::patch`].
+    fn forward(&mut self, instruction: Instruction) -> usize {
+        self.emit(instruction);
+        self.out.len() - 1
+    }
+
+    fn patch(&mut self, at: usize, target: usize) {
+        self.out[at] = with_jump(&self.out[at], target as i64 - at as i64);
+    }
+
+    /// Falls through when the condition holds, the returned jump has to be
+    /// pointed to where it goes otherwise.
+    fn test(&mut self, cond: &Cond) -> Result<usize, CompileError> {
+        let (cond, negated) = match cond {
+            Cond::Not(cond) => match cond.as_ref() {
+                Cond::Not(cond) => return self.test(cond),
+                cond => (cond, true),
+            },
+            cond => (cond, false),
+        };
+        let layout = &self.layout;
+        let jump = match cond {
+            Cond::Cmp(a, ordering, b) => {
+                Instruction::JumpRelCmp(layout.word(a)?, layout.word(b)?, *ordering, 2)
+            }
+            Cond::CmpVal(cell, ordering, val) => {
+                Instruction::JumpRelIf(layout.word(cell)?, *ordering, *val, 2)
+            }
+            Cond::Empty(cell) => Instruction::JumpRelIfStr(layout.word(cell)?, textbox(""), 2),
+            Cond::Not(_) => unreachable!(),
+        };
+
+        // A negated condition jumps away by itself, otherwise the jump skips
+        // over a jump away
+        if !negated {
+            self.emit(jump);
+            return Ok(self.forward(Instruction::Jump(0)));
+        }
+        Ok(self.forward(jump))
+    }
+
+    /// Copies the variables onto a fresh row and moves below it.
+    fn sync(&mut self, vars: &[String]) -> Result<(), CompileError> {
+        if vars.is_empty() {
+            return Ok(());
+        }
+        if self.layout.col != 0 || self.layout.adrift {
+            self.newline();
+        }
+        for var in vars {
+            let value = StackInstr::Copy(Cell::Var(var.clone()));
+            self.instruction(StackInstr::Set(var.clone(), Box::new(value)))?;
+        }
+        self.newline();
+        Ok(())
+    }
+
+    /// Rows of a block, with a line end between rows but not after the last,
+    /// so a block of one row stays on the row it starts on.
+    fn block(&mut self, rows: Vec<Vec<StackInstr>>) -> Result<(), CompileError> {
+        let count = rows.len();
+        for (i, row) in rows.into_iter().enumerate() {
+            for instruction in row {
+                self.starts.push(self.out.len());
+                self.instruction(instruction)?;
+            }
+            if i + 1 < count {
+                self.starts.push(self.out.len());
+                self.newline();
+            }
+        }
+        Ok(())
+    }
+
+    fn if_else(
+        &mut self,
+        cond: Cond,
+        then: Vec<Vec<StackInstr>>,
+        otherwise: Vec<Vec<StackInstr>>,
+    ) -> Result<(), CompileError> {
+        let entry = self.layout.clone();
+        let vars = entry.live();
+
+        let to_else = self.test(&cond)?;
+        self.block(then)?;
+        self.sync(&vars)?;
+        let to_end = self.forward(Instruction::Jump(0));
+        let then_end = self.layout.clone();
+
+        self.patch(to_else, self.out.len());
+        self.layout.restore(&entry);
+        self.block(otherwise)?;
+        self.sync(&vars)?;
+
+        self.patch(to_end, self.out.len());
+        self.layout.latest.retain(|var, _| vars.contains(var));
+        // Without variables the branches are not brought back together
+        let (a, b) = (&then_end, &self.layout);
+        self.layout.adrift =
+            vars.is_empty() && (a.adrift || b.adrift || (a.row, a.col) != (b.row, b.col));
+        Ok(())
+    }
+
+    fn repeat(
+        &mut self,
+        cond: Option<Cond>,
+        body: Vec<Vec<StackInstr>>,
+    ) -> Result<(), CompileError> {
+        let vars = self.layout.live();
+        self.sync(&vars)?;
+        let head = self.out.len();
+        let entry = self.layout.clone();
+
+        let exits = match &cond {
+            Some(cond) => vec![self.test(cond)?],
+            None => vec![],
+        };
+        self.loops.push(LoopExit {
+            vars: vars.clone(),
+            breaks: exits,
+        });
+        self.block(body)?;
+        self.sync(&vars)?;
+        self.emit(Instruction::Jump(head as i64 - self.out.len() as i64));
+
+        let exit = self.loops.pop().unwrap();
+        for at in exit.breaks {
+            self.patch(at, self.out.len());
+        }
+        self.layout.restore(&entry);
+        // Copying the variables leaves every iteration on a fresh row
+        self.layout.adrift = vars.is_empty();
+        Ok(())
+    }
+
+    /// The compiled function, which is compiled on its first call.
+    fn function(
+        &mut self,
+        name: &str,
+        args: usize,
+    ) -> Result<(Vec<Instruction>, SourceMap), CompileError> {
+        let library = self.library.clone();
+        let function = library
+            .borrow()
+            .functions
+            .get(name)
+            .cloned()
+            .ok_or(format!("unknown function `{}`", name))?;
+        if function.params.len() != args {
+            return Err(format!(
+                "`{}` takes {} values but is called with {}",
+                name,
+                function.params.len(),
+                args
+            )
+            .into());
+        }
+        if let Some(compiled) = library.borrow().compiled.get(name) {
+            return Ok(compiled.clone());
+        }
+        if library.borrow().active.iter().any(|active| active == name) {
+            return Err(format!("`{}` calls itself", name).into());
+        }
+
+        library.borrow_mut().active.push(name.to_string());
+        let header = Header::Params(name.to_string(), function.params);
+        let compiled = compile(function.body, header, library.clone());
+        library.borrow_mut().active.pop();
+
+        let compiled = compiled.map_err(|e| format!("in `{}`: {}", name, e))?;
+        library
+            .borrow_mut()
+            .compiled
+            .insert(name.to_string(), compiled.clone());
+        Ok(compiled)
+    }
+
+    /// Instructions that write a value in the cell of the cursor
+    fn value(&mut self, instruction: StackInstr) -> Result<(), CompileError> {
+        let layout = &self.layout;
+        let inst = match instruction {
+            StackInstr::Text(text) => write(text.to_vec()),
+            StackInstr::Write(val) => write(val),
+            StackInstr::Copy(cell) => Instruction::Copy(layout.word(&cell)?),
+            StackInstr::Add(a, b) => Instruction::Add(layout.word(&a)?, layout.word(&b)?),
+            StackInstr::Sub(a, b) => Instruction::Sub(layout.word(&a)?, layout.word(&b)?),
+            StackInstr::Mod(a, b) => Instruction::Mod(layout.word(&a)?, layout.word(&b)?),
+            StackInstr::Mul(a, b) => Instruction::Mul(layout.word(&a)?, layout.word(&b)?),
+            StackInstr::Div(a, b) => Instruction::Div(layout.word(&a)?, layout.word(&b)?),
+            StackInstr::Call { substack, inputs } => {
+                let inputs = layout.words(&inputs)?;
+                let header = Header::Inputs(self.loc.clone());
+                let (subcalls, map) = compile(substack, header, self.library.clone())?;
+                self.map.calls.insert(self.out.len(), map);
+
+                Instruction::Call(subcalls, inputs)
+            }
+            StackInstr::CallFn { name, args } => {
+                let args = layout.words(&args)?;
+                let (program, map) = self.function(&name, args.len())?;
+                self.map.calls.insert(self.out.len(), map);
+
+                Instruction::Call(program, args)
+            }
+            other => {
+                return Err(format!("{:?} does not write a value", other).into());
+            }
+        };
+        self.emit(inst);
+        self.layout.col += 1;
+        Ok(())
+    }
+
+    fn instruction(&mut self, instruction: StackInstr) -> Result<(), CompileError> {
+        let outer = self.loc.clone();
+        if let Some(loc) = &mut self.loc {
+            loc.instruction = instruction.to_string();
+        }
+        self.lower(instruction)?;
+        self.loc = outer;
+        Ok(())
+    }
+
+    fn lower(&mut self, instruction: StackInstr) -> Result<(), CompileError> {
+        let layout = &self.layout;
+        match instruction {
+            StackInstr::Jump(jump) => self.jump(Instruction::Jump(jump)),
+            StackInstr::JumpRelIf(cell, ordering, val, dest) => {
+                let word = layout.word(&cell)?;
+                self.jump(Instruction::JumpRelIf(word, ordering, val, dest))
+            }
+            StackInstr::JumpRelCmp(a, b, ordering, dest) => {
+                let (a, b) = (layout.word(&a)?, layout.word(&b)?);
+                self.jump(Instruction::JumpRelCmp(a, b, ordering, dest))
+            }
+            StackInstr::JumpEmpty(cell, dest) => {
+                let word = layout.word(&cell)?;
+                self.jump(Instruction::JumpRelIfStr(word, textbox(""), dest))
+            }
+            StackInstr::Ret(cell) => {
+                let word = layout.word(&cell)?;
+                self.emit(Instruction::Circle(word))
+            }
+            StackInstr::Break => self.emit(Instruction::BreakPoint),
+            StackInstr::If {
+                cond,
+                then,
+                otherwise,
+            } => self.if_else(cond, then, otherwise)?,
+            StackInstr::While { cond, body } => self.repeat(Some(cond), body)?,
+            StackInstr::Loop(body) => self.repeat(None, body)?,
+            StackInstr::BreakLoop => {
+                let vars = match self.loops.last() {
+                    Some(exit) => exit.vars.clone(),
+                    None => return Err("`BreakLoop` outside of a loop".to_string().into()),
+                };
+                self.sync(&vars)?;
+                let at = self.forward(Instruction::Jump(0));
+                self.loops.last_mut().unwrap().breaks.push(at);
+            }
+            StackInstr::Set(name, value) => {
+                let next = self.layout.columns.len() as i32;
+                let col = *self.layout.columns.entry(name.clone()).or_insert(next);
+                self.goto_column(col);
+                self.value(*value)?;
+                self.layout.latest.insert(name, self.layout.row);
+            }
+            value => self.value(value)?,
+        }
+        Ok(())
+    }
+
+    /// Points the jumps at the same stacker instruction they pointed at
+    /// before extra instructions were inserted.
+    fn patch_jumps(&mut self) {
+        for &(at, from) in &self.jumps {
+            let Some(rel) = jump_offset(&self.out[at]) else {
+                continue;
+            };
+            let target = from as i64 + rel;
+            let target = if target < 0 {
+                target
+            } else if target as usize >= self.starts.len() {
+                (self.out.len() + target as usize - self.starts.len()) as i64
+            } else {
+                self.starts[target as usize] as i64
             };
-            stack.push(inst)
+            self.out[at] = with_jump(&self.out[at], target - at as i64);
         }
-        stack.push(write("\n"))
     }
+}
+
+/// Compiles a stacker program, one row per line. Jumps count the
+/// instructions of the lines plus one for every line end, blocks are lowered
+/// to jumps of their own.
+///
+/// Also returns which stacker instruction every compiled instruction came
+/// from, which only holds as long as the program is not optimized.
+pub fn compile_stacker(
+    lines: Vec<Vec<StackInstr>>,
+) -> Result<(Vec<Instruction>, SourceMap), CompileError> {
+    compile_program(lines.into())
+}
 
-    stack
+/// Compiles a program like [`compile_stacker`], every function it calls is
+/// compiled once and copied into each call.
+pub fn compile_program(program: Program) -> Result<(Vec<Instruction>, SourceMap), CompileError> {
+    let library = Library {
+        functions: program.functions,
+        ..Default::default()
+    };
+    compile(
+        program.lines,
+        Header::Nothing,
+        Rc::new(RefCell::new(library)),
+    )
+}
+
+/// Called programs start below the row their inputs are written on, the
+/// line end is attributed to the call or the function.
+fn compile(
+    lines: Vec<Vec<StackInstr>>,
+    header: Header,
+    library: Rc<RefCell<Library>>,
+) -> Result<(Vec<Instruction>, SourceMap), CompileError> {
+    let mut compiler = Compiler {
+        library,
+        ..Default::default()
+    };
+    match header {
+        Header::Nothing => {}
+        Header::Inputs(call) => {
+            compiler.loc = call;
+            compiler.newline();
+        }
+        Header::Params(name, params) => {
+            compiler.loc = Some(SourceLoc {
+                line: 0,
+                column: 0,
+                instruction: format!("fn {}({})", name, params.join(", ")),
+            });
+            for (col, param) in params.into_iter().enumerate() {
+                compiler.layout.columns.insert(param.clone(), col as i32);
+                compiler.layout.latest.insert(param, 0);
+                compiler.layout.col += 1;
+            }
+            compiler.newline();
+        }
+    }
+    for (i, line) in lines.into_iter().enumerate() {
+        let end = line.len();
+        for (column, instruction) in line.into_iter().enumerate() {
+            compiler.loc = Some(SourceLoc {
+                line: i,
+                column,
+                instruction: String::new(),
+            });
+            compiler.starts.push(compiler.out.len());
+            compiler.instruction(instruction)?;
+        }
+        compiler.loc = Some(SourceLoc {
+            line: i,
+            column: end,
+            instruction: "line end".to_string(),
+        });
+        compiler.starts.push(compiler.out.len());
+        compiler.newline();
+    }
+    compiler.patch_jumps();
+
+    Ok((compiler.out, compiler.map))
 }
 
 pub fn gcd() -> Vec<Vec<StackInstr>> {
     vec![
         vec![text("a"), text("b")],
         vec![
-            StackInstr::Copy(Pos { x: 0, y: -2 }),
-            StackInstr::Copy(Pos { x: 0, y: -2 }),
+            StackInstr::Copy((0, -2).into()),
+            StackInstr::Copy((0, -2).into()),
         ],
         vec![
-            StackInstr::JumpRelCmp(Pos { x: 0, y: -1 }, Pos { x: 1, y: -1 }, Ordering::Equal, 8),
-            StackInstr::JumpRelCmp(Pos { x: 0, y: -1 }, Pos { x: 1, y: -1 }, Ordering::Less, 4),
-            StackInstr::Sub(Pos { x: 0, y: -1 }, Pos { x: 1, y: -1 }),
-            StackInstr::Copy(Pos { x: 0, y: -1 }),
+            StackInstr::JumpRelCmp((0, -1).into(), (1, -1).into(), Ordering::Equal, 8),
+            StackInstr::JumpRelCmp((0, -1).into(), (1, -1).into(), Ordering::Less, 4),
+            StackInstr::Sub((0, -1).into(), (1, -1).into()),
+            StackInstr::Copy((0, -1).into()),
             StackInstr::Jump(-5),
-            StackInstr::Copy(Pos { x: 0, y: -1 }),
-            StackInstr::Sub(Pos { x: 0, y: -1 }, Pos { x: -1, y: -1 }),
+            StackInstr::Copy((0, -1).into()),
+            StackInstr::Sub((0, -1).into(), (-1, -1).into()),
             StackInstr::Jump(-8),
             StackInstr::Break,
         ],
     ]
 }
 
+/// Bubble sort of the numbers on the row above, one pass per row.
 pub fn sort() -> Vec<Vec<StackInstr>> {
-    vec![
-        vec![
-            StackInstr::JumpEmpty(Pos { x: 1, y: -1 }, 8),
-            StackInstr::JumpRelCmp(
-                Pos { x: 0, y: -1 },
-                Pos { x: 1, y: -1 },
-                Ordering::Greater,
-                3,
-            ),
-            StackInstr::Copy(Pos { x: 0, y: -1 }),
-            StackInstr::Jump(-3),
-            StackInstr::Copy(Pos { x: 1, y: -1 }),
-            StackInstr::Copy(Pos { x: -1, y: -1 }),
-            StackInstr::Jump(-6),
-            StackInstr::Break,
-            // Check if we need to copy last value
-            StackInstr::Copy(Pos { x: 0, y: -1 }),
-        ],
-        vec![StackInstr::Jump(-9)],
-    ]
+    let pass = StackInstr::While {
+        cond: Cond::Not(Box::new(Cond::Empty((1, -1).into()))),
+        body: vec![vec![StackInstr::If {
+            cond: Cond::Cmp((0, -1).into(), Ordering::Greater, (1, -1).into()),
+            then: vec![vec![
+                StackInstr::Copy((1, -1).into()),
+                StackInstr::Copy((-1, -1).into()),
+            ]],
+            otherwise: vec![vec![StackInstr::Copy((0, -1).into())]],
+        }]],
+    };
+
+    // The last number is left over when the row above runs out
+    vec![vec![StackInstr::Loop(vec![
+        vec![pass, StackInstr::Copy((0, -1).into())],
+        vec![],
+    ])]]
 }
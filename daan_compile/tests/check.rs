@@ -0,0 +1,89 @@
+//! Kinds of cells found by the checker.
+
+mod common;
+
+use common::with_args;
+use daan_compile::{
+    check::{check_program, Problem},
+    parse::parse_stacker,
+    stacker::*,
+};
+
+fn problems(source: &str) -> Vec<(usize, usize, Problem)> {
+    check_program(&parse_stacker(source).unwrap())
+        .into_iter()
+        .map(|error| (error.loc.line, error.loc.column, error.problem))
+        .collect()
+}
+
+#[test]
+fn arithmetic_on_text() {
+    assert_eq!(
+        problems("text \"a\"; write 1\nadd [0,-1] [1,-1]; ret [-1,0]"),
+        vec![(1, 0, Problem::TextArithmetic((0, -1).into()))]
+    );
+}
+
+#[test]
+fn text_compared_with_number() {
+    assert_eq!(
+        problems("text \"a\"; write 1\njmp_cmp [0,-1] [1,-1] < 1; ret [0,-1]"),
+        vec![(1, 0, Problem::Mismatched((0, -1).into(), (1, -1).into()))]
+    );
+    assert_eq!(
+        problems("text \"a\"\nif [0,-1] > 3 { write 1 } else { write 2 }; ret [-1,0]"),
+        vec![(1, 0, Problem::TextComparedTo((0, -1).into(), 3.))]
+    );
+}
+
+#[test]
+fn cells_never_written() {
+    assert_eq!(
+        problems("write 1\nadd [0,-1] [1,-1]; ret [-1,0]"),
+        vec![(1, 0, Problem::NeverWritten((1, -1).into()))]
+    );
+    // Looking for empty cells is fine
+    assert_eq!(problems("write 1; jmp_empty [0,1] 1; ret [-1,0]"), vec![]);
+}
+
+#[test]
+fn text_copied_around() {
+    let source = "text \"total\"
+        copy [0,-1]; write 2
+        sub [1,-1] [0,-1]; ret [-1,0]";
+    assert_eq!(
+        problems(source),
+        vec![(2, 0, Problem::TextArithmetic((0, -1).into()))]
+    );
+}
+
+#[test]
+fn through_calls_and_variables() {
+    let source = "fn twice(x)
+            add x x; ret [-1,0]
+        end
+        name = text \"n\"; call twice(name); ret [-1,0]";
+    let errors = check_program(&parse_stacker(source).unwrap());
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].function.as_deref(), Some("twice"));
+    assert_eq!(errors[0].problem, Problem::TextArithmetic("x".into()));
+}
+
+#[test]
+fn example_programs() {
+    assert_eq!(check_program(&with_args(&[1071., 462.], gcd())), vec![]);
+    let squares = parse_stacker(include_str!("../programs/squares.stack")).unwrap();
+    assert_eq!(check_program(&with_args(&[3., 4.], squares)), vec![]);
+    let fib = parse_stacker(include_str!("../programs/fib.stack")).unwrap();
+    assert_eq!(check_program(&fib), vec![]);
+
+    // A pass of sort that ends with a swap copies the empty cell after it
+    let errors = check_program(&with_args(&[3., 1.], sort()));
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].loc.instruction, "copy [0,-1]");
+
+    // Without the numbers it is called with, gcd reads empty cells
+    assert!(check_program(&gcd().into())
+        .iter()
+        .all(|error| matches!(error.problem, Problem::NeverWritten(_))));
+}
@@ -0,0 +1,11 @@
+//! Helpers shared by the test files.
+
+use daan_compile::stacker::{Program, StackInstr};
+
+/// The program with a first line that writes the arguments.
+pub fn with_args(args: &[f64], program: impl Into<Program>) -> Program {
+    let mut program = program.into();
+    let args = args.iter().map(|&arg| StackInstr::Write(arg)).collect();
+    program.lines.insert(0, args);
+    program
+}
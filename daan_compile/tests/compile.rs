@@ -0,0 +1,458 @@
+//! Compiled stacker programs run on a `PaperVM` against the interpreter.
+
+mod common;
+
+use common::with_args;
+use daan_compile::{
+    interpret::Interpreter,
+    lang::{lower, parse_lang},
+    parse::parse_stacker,
+    stacker::*,
+};
+use papier::papervm::{CharCell, Instruction, PaperVM, StepResult};
+
+const MAX_STEPS: u64 = 100_000;
+
+struct Run {
+    result: Result<String, String>,
+    sheet: String,
+}
+
+fn text(chars: Vec<char>) -> String {
+    chars
+        .into_iter()
+        .collect::<String>()
+        .replace('_', " ")
+        .trim()
+        .to_string()
+}
+
+fn run_vm(program: &Program) -> Run {
+    let mut vm = PaperVM::<CharCell>::new(compile_program(program.clone()).unwrap().0);
+    let mut result = Err(format!("step limit of {} reached", MAX_STEPS));
+    for _ in 0..MAX_STEPS {
+        match vm.try_step() {
+            Ok(step) if step.is_finished() => {
+                result = Ok(text(vm.result::<Vec<char>>().unwrap()));
+                break;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                result = Err(e.to_string());
+                break;
+            }
+        }
+    }
+    Run {
+        result,
+        sheet: vm.print(),
+    }
+}
+
+fn run_interpreter(program: &Program) -> Run {
+    let mut interpreter = Interpreter::new(MAX_STEPS);
+    let result = interpreter
+        .run(program)
+        .map(text)
+        .map_err(|e| e.to_string());
+    Run {
+        result,
+        sheet: interpreter.print(),
+    }
+}
+
+/// Checks that both return the same, or both fail, and returns the result.
+fn check_result(program: &Program) -> (Run, Run) {
+    let (vm, interpreted) = (run_vm(program), run_interpreter(program));
+    match (&vm.result, &interpreted.result) {
+        (Ok(a), Ok(b)) => assert_eq!(a, b, "compiled and interpreted results differ"),
+        (Err(_), Err(_)) => {}
+        (a, b) => panic!("compiled: {:?}, interpreted: {:?}", a, b),
+    }
+    (vm, interpreted)
+}
+
+/// Programs without variables also have to write the same sheet.
+fn check(program: &Program) -> Result<String, String> {
+    let (vm, interpreted) = check_result(program);
+    assert_eq!(
+        vm.sheet, interpreted.sheet,
+        "compiled:\n{}\ninterpreted:\n{}",
+        vm.sheet, interpreted.sheet
+    );
+    vm.result
+}
+
+fn check_source(source: &str) -> Result<String, String> {
+    check(&parse_stacker(source).unwrap())
+}
+
+#[test]
+fn text_and_write() {
+    assert_eq!(
+        check_source("text \"ab\"; write 12; ret [-2,0]"),
+        Ok("ab".into())
+    );
+    assert_eq!(
+        check_source("text \"ab\"; write -3.5; ret [-1,0]"),
+        Ok("-3.5".into())
+    );
+}
+
+#[test]
+fn copy() {
+    assert_eq!(
+        check_source("write 7\ncopy [0,-1]; ret [-1,0]"),
+        Ok("7".into())
+    );
+}
+
+#[test]
+fn add_uses_both_operands() {
+    let source = "write 2; write 40\nadd [0,-1] [1,-1]; ret [-1,0]";
+    assert_eq!(check_source(source), Ok("42".into()));
+}
+
+#[test]
+fn arithmetic() {
+    let source = "write 17; write 5
+        sub [0,-1] [1,-1]; mod [-1,-1] [0,-1]; mul [-2,-1] [-1,-1]; div [-3,-1] [-2,-1]
+        ret CELL";
+    for (cell, expected) in [
+        ("[0,-1]", "12"),
+        ("[1,-1]", "2"),
+        ("[2,-1]", "85"),
+        ("[3,-1]", "3.4"),
+    ] {
+        assert_eq!(
+            check_source(&source.replace("CELL", cell)),
+            Ok(expected.into())
+        );
+    }
+}
+
+#[test]
+fn jump() {
+    assert_eq!(
+        check_source("write 1; jmp 2; write 2; write 3; ret [-1,0]"),
+        Ok("3".into())
+    );
+}
+
+#[test]
+fn jump_across_line_ends() {
+    let source = "write 1; jmp 3; write 2\nwrite 3\nwrite 4; ret [-1,0]";
+    assert_eq!(check_source(source), Ok("4".into()));
+}
+
+#[test]
+fn conditional_jumps() {
+    for (jump, taken) in [
+        ("jmp_if [0,-1] > 3 3", "yes"),
+        ("jmp_if [0,-1] < 3 3", "no"),
+        ("jmp_cmp [0,-1] [1,-1] = 3", "yes"),
+        ("jmp_cmp [0,-1] [1,-1] > 3", "no"),
+        ("jmp_empty [2,-1] 3", "yes"),
+        ("jmp_empty [1,-1] 3", "no"),
+    ] {
+        let source = format!(
+            "write 4; write 4\n{}; text \"no\"; jmp 2; text \"yes\"; ret [-1,0]",
+            jump
+        );
+        assert_eq!(check_source(&source), Ok(taken.into()), "{}", jump);
+    }
+}
+
+#[test]
+fn breakpoint_does_nothing() {
+    assert_eq!(
+        check_source("write 1; breakpoint; ret [-1,0]"),
+        Ok("1".into())
+    );
+}
+
+#[test]
+fn running_off_the_end_fails() {
+    assert!(check_source("write 1").is_err());
+}
+
+#[test]
+fn call() {
+    let source = "fn double(x)
+            add [0,-1] [0,-1]; ret [-1,0]
+        end
+        write 21; write 5
+        call double([0,-1]); call double([0,-1]); add [-2,0] [-1,0]; ret [-1,0]";
+    assert_eq!(check_source(source), Ok("52".into()));
+}
+
+#[test]
+fn raw_call() {
+    // The inputs are written on the first row of the called sheet
+    let double = vec![vec![
+        StackInstr::Add((0, -1).into(), (1, -1).into()),
+        StackInstr::Ret((-1, 0).into()),
+    ]];
+    let program: Program = vec![
+        vec![StackInstr::Write(21.), StackInstr::Write(5.)],
+        vec![
+            StackInstr::Call {
+                substack: double,
+                inputs: vec![(0, -1).into(), (1, -1).into()],
+            },
+            StackInstr::Ret((-1, 0).into()),
+        ],
+    ]
+    .into();
+    assert_eq!(check(&program), Ok("26".into()));
+}
+
+#[test]
+fn if_else() {
+    for (val, expected) in [(1., "small"), (7., "big")] {
+        let program =
+            parse_stacker("if [0,-1] > 5 { text \"big\" } else { text \"small\" }; ret [-1,0]")
+                .unwrap();
+        assert_eq!(check(&with_args(&[val], program)), Ok(expected.into()));
+    }
+}
+
+#[test]
+fn if_on_lines() {
+    let source = "write 3
+        if [0,-1] = 3
+            text \"three\"
+        else
+            text \"other\"
+        end; ret [0,-1]";
+    assert_eq!(check_source(source), Ok("three".into()));
+}
+
+#[test]
+fn while_loop() {
+    let source = "write 5; write 1
+        while [0,-1] > 0
+            sub [0,-1] [1,-1]; copy [0,-1]
+        end; ret [1,-1]";
+    assert_eq!(check_source(source), Ok("1".into()));
+}
+
+#[test]
+fn loop_and_break() {
+    let source = "write 0; write 3
+        loop
+            add [0,-1] [1,-1]; copy [0,-1]; if [-2,0] > 10 { break }
+        end; ret [-2,0]";
+    assert_eq!(check_source(source), Ok("12".into()));
+}
+
+#[test]
+fn sort_pass() {
+    let source = "write 5; write 3; write 9; write 1
+        while not empty [1,-1] { if [0,-1] > [1,-1] { copy [1,-1]; copy [-1,-1] } else { copy [0,-1] } }; copy [0,-1]; ret [-5,0]";
+    assert_eq!(check_source(source), Ok("3".into()));
+}
+
+#[test]
+fn sort_file_is_sort() {
+    let source = include_str!("../programs/sort.stack");
+    assert_eq!(parse_stacker(source).unwrap(), sort().into());
+}
+
+#[test]
+fn gcd_program() {
+    let source = include_str!("../programs/gcd.stack");
+    assert_eq!(parse_stacker(source).unwrap(), gcd().into());
+
+    // Stops at a breakpoint at the end instead of returning
+    let (vm, interpreted) = check_result(&with_args(&[1071., 462.], gcd()));
+    assert_eq!(vm.sheet, interpreted.sheet);
+    assert!(vm.sheet.lines().last().unwrap().contains("________21"));
+}
+
+#[test]
+fn variables() {
+    let source = "a = write 3; b = write 4
+        c = mul a a; d = mul b b
+        e = add c d
+        ret e";
+    let (vm, _) = check_result(&parse_stacker(source).unwrap());
+    assert_eq!(vm.result, Ok("25".into()));
+}
+
+#[test]
+fn variables_in_blocks() {
+    let source = "n = write 5; s = write 0; one = write 1
+        while n > 0
+            s = add s n; n = sub n one
+            if s > 12 { break }
+        end
+        ret s";
+    let (vm, _) = check_result(&parse_stacker(source).unwrap());
+    assert_eq!(vm.result, Ok("14".into()));
+}
+
+#[test]
+fn variables_after_blocks() {
+    // The branches and the loop end on different columns
+    for source in [
+        "write 5\nif [0,-1] > 3 { a = write 1; b = write 2 } else { write 3 }; c = write 7\nret c",
+        "write 1\nif [0,-1] > 3 { a = write 1; b = write 2 } else { write 3 }; c = write 7\nret c",
+        "loop { x = write 1; break }; y = write 2\nret y",
+        "write 5\nwhile [0,-1] > 3 { x = write 1; break }; y = write 2\nret y",
+    ] {
+        let program = parse_stacker(source).unwrap();
+        let (vm, interpreted) = check_result(&program);
+        assert!(vm.result.is_ok(), "{}: {:?}", source, vm.result);
+        assert_eq!(vm.result, interpreted.result, "{}", source);
+    }
+    let program = parse_stacker("loop { x = write 1; break }; y = write 2\nret y").unwrap();
+    assert_eq!(check_result(&program).0.result, Ok("2".into()));
+}
+
+#[test]
+fn unknown_variable() {
+    let program = parse_stacker("copy x").unwrap();
+    assert!(compile_program(program.clone()).is_err());
+    assert!(Interpreter::new(MAX_STEPS).run(&program).is_err());
+}
+
+#[test]
+fn set_to_no_value() {
+    let program = parse_stacker("write 1\nx = break").unwrap();
+    let compiled = compile_program(program.clone()).unwrap_err();
+    let interpreted = Interpreter::new(MAX_STEPS).run(&program).unwrap_err();
+    assert_eq!(compiled.to_string(), interpreted.to_string());
+}
+
+#[test]
+fn lang_programs() {
+    for (source, args, expected) in [
+        (
+            include_str!("../programs/gcd.daan"),
+            vec![1071., 462.],
+            "21",
+        ),
+        (
+            include_str!("../programs/functions.daan"),
+            vec![10.],
+            "95.8333335",
+        ),
+        ("input n; return -n * (n - 1) / 4;", vec![5.], "-5"),
+        (
+            "x = 2; if x >= 2 { x = x + 1; } else { x = 0; } return x;",
+            vec![],
+            "3",
+        ),
+        // Variables first assigned in a block can be read after it
+        (
+            "x = 2; if x > 1 { y = 1; } else { y = 2; } return y;",
+            vec![],
+            "1",
+        ),
+        (
+            "i = 0; while i < 3 { last = i; i = i + 1; } return last;",
+            vec![],
+            "2",
+        ),
+    ] {
+        let program = with_args(&args, lower(&parse_lang(source).unwrap()).unwrap());
+        let (vm, _) = check_result(&program);
+        assert_eq!(vm.result, Ok(expected.into()), "{}", source);
+    }
+}
+
+#[test]
+fn source_map() {
+    let source = "fn double(x)
+            add [0,-1] [0,-1]; ret [-1,0]
+        end
+        write 21; if [-1,0] > 3 { text \"big\" }
+        call double([-2,-1]); ret [-1,0]";
+    let (program, map) = compile_program(parse_stacker(source).unwrap()).unwrap();
+    assert_eq!(map.locs.len(), program.len());
+
+    let mut vm = PaperVM::<CharCell>::new(program);
+    let mut steps = vec![];
+    while let StepResult::Running(state) = vm.step() {
+        let loc = map
+            .get(&state.trace)
+            .expect("every instruction has a source");
+        steps.push((state.trace, loc.to_string()));
+    }
+
+    assert!(steps.contains(&(vec![0], "0:0 write 21".into())));
+    assert!(steps.iter().any(|(_, loc)| loc == "0:1 text \"big\""));
+    let add = steps
+        .iter()
+        .find(|(_, loc)| loc.ends_with("add [0,-1] [0,-1]"))
+        .unwrap();
+    assert_eq!(add.0.len(), 2, "the add is inside the call");
+    assert_eq!(add.1, "0:0 add [0,-1] [0,-1]");
+}
+
+#[test]
+fn functions_with_params() {
+    let source = include_str!("../programs/squares.stack");
+    assert_eq!(
+        check(&with_args(&[3., 4.], parse_stacker(source).unwrap())),
+        Ok("25".into())
+    );
+
+    let source = "fn sub3(a, b, c)
+            d = sub a b; e = sub d c; ret e
+        end
+        fn neg(x)
+            zero = write 0; call sub3(zero, x, zero); ret [-1,0]
+        end
+        write 20; write 1; write 2
+        call sub3([0,-1], [1,-1], [2,-1]); call neg([-1,0]); ret [-1,0]";
+    let (vm, _) = check_result(&parse_stacker(source).unwrap());
+    assert_eq!(vm.result, Ok("-17".into()));
+}
+
+#[test]
+fn functions_are_compiled_once() {
+    let source = "fn inc(x)
+            write 1; add x [-1,0]; ret [-1,0]
+        end
+        write 1
+        call inc([0,-1]); call inc([-1,0]); ret [-1,0]";
+    let program = parse_stacker(source).unwrap();
+    assert_eq!(check(&program), Ok("3".into()));
+
+    let (compiled, _) = compile_program(program).unwrap();
+    let calls: Vec<_> = compiled
+        .iter()
+        .filter_map(|instruction| match instruction {
+            Instruction::Call(program, _) => Some(program),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(calls.len(), 2);
+    assert_eq!(format!("{:?}", calls[0]), format!("{:?}", calls[1]));
+}
+
+#[test]
+fn bad_function_calls() {
+    assert!(parse_stacker("call missing([0,-1])").is_err());
+    for source in [
+        "fn f(a, b)\n ret a\nend\nwrite 1; call f([-1,0]); ret [-1,0]",
+        "fn f(a)\n call f(a); ret a\nend\nwrite 1; call f([-1,0]); ret [-1,0]",
+    ] {
+        let program = parse_stacker(source).unwrap();
+        assert!(compile_program(program.clone()).is_err(), "{}", source);
+        assert!(
+            Interpreter::new(MAX_STEPS).run(&program).is_err(),
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn stray_bracket() {
+    let e = parse_stacker("write 1\nwrite 2 ]; ret [-1,0]").unwrap_err();
+    assert_eq!(e.line, 2);
+    assert_eq!(e.message, "unexpected `]`");
+}
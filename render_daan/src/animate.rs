@@ -0,0 +1,342 @@
+//! Animations of a program being worked out on paper, as GIF or APNG.
+//!
+//! The VM is run twice: once to find how large the sheets get, so every
+//! frame can be drawn on the same [`Grid`], and once to draw the frames.
+//! Sheets of calls are laid on top of the sheet that called them and slide
+//! in from below when the call is made. The cursor of the sheets that are
+//! being written on is drawn as a pen.
+
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+use ab_glyph::{FontRef, InvalidFont, PxScale};
+use imageproc::{
+    drawing::{draw_hollow_rect_mut, draw_polygon_mut},
+    image::{
+        codecs::gif::{GifEncoder, Repeat},
+        Delay, Frame, ImageError, Rgba, RgbaImage,
+    },
+    point::Point,
+    rect::Rect,
+};
+use papier::papervm::{Instruction, MemoryCell, PaperVM, Pos, StepResult, VmError};
+
+use crate::grid::{bounds, Grid};
+use crate::render::{draw_paper, draw_sheet, Paper, DESK, EDGE, INK};
+
+const PEN: Rgba<u8> = Rgba([40, 70, 160, 255]);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+}
+
+impl AnimationFormat {
+    /// The format for the extension of the path: `.gif`, or `.png` or
+    /// `.apng` for an animated PNG.
+    pub fn from_path(path: &Path) -> Option<AnimationFormat> {
+        match path.extension()?.to_str()? {
+            "gif" => Some(AnimationFormat::Gif),
+            "png" | "apng" => Some(AnimationFormat::Apng),
+            _ => None,
+        }
+    }
+}
+
+pub struct AnimationOptions<'a> {
+    /// TrueType or OpenType font to write with
+    pub font: &'a [u8],
+    pub scale: f32,
+    /// Number of steps between frames
+    pub every: usize,
+    /// Time a frame is shown, in milliseconds
+    pub delay: u16,
+    /// Number of frames a called sheet takes to slide in
+    pub slide: usize,
+    /// Its margin is at least two lines, to keep the pen in the picture
+    pub paper: Paper,
+}
+
+#[derive(Debug)]
+pub enum AnimationError {
+    Font(InvalidFont),
+    Vm(VmError),
+    Io(io::Error),
+    Gif(ImageError),
+    Png(png::EncodingError),
+}
+
+impl Display for AnimationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnimationError::Font(e) => write!(f, "invalid font: {}", e),
+            AnimationError::Vm(e) => write!(f, "program failed: {}", e),
+            AnimationError::Io(e) => write!(f, "{}", e),
+            AnimationError::Gif(e) => write!(f, "could not write GIF: {}", e),
+            AnimationError::Png(e) => write!(f, "could not write APNG: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AnimationError {}
+
+impl From<InvalidFont> for AnimationError {
+    fn from(e: InvalidFont) -> Self {
+        AnimationError::Font(e)
+    }
+}
+
+impl From<VmError> for AnimationError {
+    fn from(e: VmError) -> Self {
+        AnimationError::Vm(e)
+    }
+}
+
+impl From<io::Error> for AnimationError {
+    fn from(e: io::Error) -> Self {
+        AnimationError::Io(e)
+    }
+}
+
+impl From<ImageError> for AnimationError {
+    fn from(e: ImageError) -> Self {
+        AnimationError::Gif(e)
+    }
+}
+
+impl From<png::EncodingError> for AnimationError {
+    fn from(e: png::EncodingError) -> Self {
+        AnimationError::Png(e)
+    }
+}
+
+/// The sheets on the desk, from the bottom one up. Only the last sheets,
+/// without calls or forks of their own, are being written on.
+fn sheets<T: MemoryCell>(vm: &PaperVM<T>) -> Vec<&PaperVM<T>> {
+    let mut desk = vec![vm];
+    if let Some(subroutine) = &vm.subroutine {
+        desk.extend(sheets(subroutine));
+    } else {
+        for fork in &vm.forks {
+            desk.extend(sheets(fork));
+        }
+    }
+    desk
+}
+
+fn is_writing<T: MemoryCell>(vm: &PaperVM<T>) -> bool {
+    vm.subroutine.is_none() && vm.forks.is_empty()
+}
+
+/// A frame to draw: the sheets on the desk, where the ones from `new` on are
+/// sliding in and are `slid` of the way there.
+struct Shot<'a, T: MemoryCell> {
+    sheets: Vec<&'a PaperVM<T>>,
+    new: usize,
+    slid: f32,
+}
+
+/// Runs the VM to the end, calling `frame` for every frame of the animation.
+fn film<T: MemoryCell>(
+    mut vm: PaperVM<T>,
+    options: &AnimationOptions,
+    mut frame: impl FnMut(Shot<T>) -> Result<(), AnimationError>,
+) -> Result<(), AnimationError> {
+    let every = options.every.max(1);
+    let mut steps = 0;
+    frame(Shot {
+        sheets: sheets(&vm),
+        new: 1,
+        slid: 1.,
+    })?;
+
+    loop {
+        let before = sheets(&vm).len();
+        let state = match vm.try_step()? {
+            StepResult::Finished => break,
+            StepResult::Running(state) => state,
+        };
+        steps += 1;
+
+        let called = matches!(
+            state.instruction,
+            Instruction::Call(..) | Instruction::Fork(..)
+        );
+        if called && options.slide > 0 {
+            for i in 1..=options.slide {
+                frame(Shot {
+                    sheets: sheets(&vm),
+                    new: before,
+                    slid: i as f32 / options.slide as f32,
+                })?;
+            }
+        } else if steps % every == 0 {
+            frame(Shot {
+                sheets: sheets(&vm),
+                new: before,
+                slid: 1.,
+            })?;
+        }
+    }
+
+    // The result is only circled by the last step
+    frame(Shot {
+        sheets: sheets(&vm),
+        new: 1,
+        slid: 1.,
+    })
+}
+
+/// What a film needs to fit on its frames.
+struct Extent {
+    frames: u32,
+    min: Pos,
+    max: Pos,
+    depth: usize,
+}
+
+impl Extent {
+    fn of<T: MemoryCell>(
+        vm: PaperVM<T>,
+        options: &AnimationOptions,
+    ) -> Result<Extent, AnimationError> {
+        let mut extent = Extent {
+            frames: 0,
+            min: Pos(0, 0),
+            max: Pos(0, 0),
+            depth: 1,
+        };
+        film(vm, options, |shot| {
+            extent.frames += 1;
+            extent.depth = extent.depth.max(shot.sheets.len());
+            for sheet in shot.sheets {
+                let cursor = sheet.cursor();
+                let (min, max) = bounds(sheet).unwrap_or((cursor, cursor));
+                for Pos(x, y) in [min, max, cursor] {
+                    extent.min = Pos(extent.min.0.min(x), extent.min.1.min(y));
+                    extent.max = Pos(extent.max.0.max(x), extent.max.1.max(y));
+                }
+            }
+            Ok(())
+        })?;
+        Ok(extent)
+    }
+}
+
+/// A pen with its nib at the point, leaning to the right.
+fn draw_pen(canvas: &mut RgbaImage, (x, y): (i32, i32), size: f32) {
+    let at = |dx: f32, dy: f32| Point::new(x + (dx * size) as i32, y - (dy * size) as i32);
+    draw_polygon_mut(
+        canvas,
+        &[at(0.12, 0.3), at(0.55, 1.7), at(0.75, 1.62), at(0.3, 0.22)],
+        PEN,
+    );
+    draw_polygon_mut(canvas, &[at(0., 0.), at(0.12, 0.3), at(0.3, 0.22)], INK);
+}
+
+struct Camera<'a> {
+    font: FontRef<'a>,
+    scale: PxScale,
+    grid: Grid,
+    paper: Paper,
+    /// Distance between a sheet and the one on top of it
+    step: i32,
+    width: u32,
+    height: u32,
+}
+
+impl Camera<'_> {
+    fn take<T: MemoryCell>(&self, shot: Shot<T>) -> RgbaImage {
+        let mut canvas = RgbaImage::from_pixel(self.width, self.height, DESK);
+        let (width, height) = (
+            self.grid.width().ceil() as u32,
+            self.grid.height().ceil() as u32,
+        );
+
+        for (i, sheet) in shot.sheets.into_iter().enumerate() {
+            let mut offset = (i as i32 * self.step, i as i32 * self.step);
+            if i >= shot.new {
+                offset.1 += ((1. - shot.slid) * self.height as f32) as i32;
+            }
+
+            draw_paper(&mut canvas, &self.grid, &self.paper, &self.font, offset);
+            let rect = Rect::at(offset.0, offset.1).of_size(width, height);
+            draw_hollow_rect_mut(&mut canvas, rect, EDGE);
+            draw_sheet(
+                &mut canvas,
+                sheet,
+                &self.grid,
+                &self.font,
+                self.scale,
+                offset,
+            );
+
+            if is_writing(sheet) {
+                let Pos(x, y) = sheet.cursor();
+                let nib = (
+                    offset.0 + self.grid.text_left(x) as i32,
+                    offset.1 + self.grid.baseline(y) as i32,
+                );
+                draw_pen(&mut canvas, nib, self.grid.line_height);
+            }
+        }
+        canvas
+    }
+}
+
+/// Animates the program running on the VM and writes it to the path.
+pub fn animate<T: MemoryCell>(
+    vm: PaperVM<T>,
+    path: &Path,
+    format: AnimationFormat,
+    options: &AnimationOptions,
+) -> Result<(), AnimationError> {
+    let extent = Extent::of(vm.clone(), options)?;
+
+    let scale = PxScale::from(options.scale);
+    let font = FontRef::try_from_slice(options.font)?;
+    // The pen sticks out of the sheet by about two lines
+    let margin = options.paper.margin.max(2.) * options.scale;
+    let grid = options.paper.fit(Grid::from_bounds(
+        extent.min, extent.max, &font, scale, margin,
+    ));
+    let step = (options.scale / 2.) as i32;
+    let stacked = (extent.depth - 1) as u32 * step as u32;
+    let camera = Camera {
+        width: grid.width().ceil() as u32 + stacked,
+        height: grid.height().ceil() as u32 + stacked,
+        font,
+        scale,
+        grid,
+        paper: options.paper,
+        step,
+    };
+
+    let file = BufWriter::new(File::create(path)?);
+    match format {
+        AnimationFormat::Gif => {
+            let mut encoder = GifEncoder::new_with_speed(file, 10);
+            encoder.set_repeat(Repeat::Infinite)?;
+            let delay = Delay::from_numer_denom_ms(options.delay as u32, 1);
+            film(vm, options, |shot| {
+                let frame = Frame::from_parts(camera.take(shot), 0, 0, delay);
+                Ok(encoder.encode_frame(frame)?)
+            })
+        }
+        AnimationFormat::Apng => {
+            let mut encoder = png::Encoder::new(file, camera.width, camera.height);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.set_animated(extent.frames, 0)?;
+            encoder.set_frame_delay(options.delay, 1000)?;
+            let mut writer = encoder.write_header()?;
+            film(vm, options, |shot| {
+                Ok(writer.write_image_data(camera.take(shot).as_raw())?)
+            })?;
+            Ok(writer.finish()?)
+        }
+    }
+}
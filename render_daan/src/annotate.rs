@@ -0,0 +1,131 @@
+//! Sheets annotated with what wrote them.
+//!
+//! Every character is written in the colour of the kind of instruction that
+//! last wrote its cell. Notes in front of every row can give the index of
+//! the instruction that wrote the start of it, so a sheet can be read next
+//! to the listing of its program.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use ab_glyph::{Font, PxScale, ScaleFont};
+use imageproc::{
+    drawing::{draw_text_mut, text_size},
+    image::{ImageError, Rgba, RgbaImage},
+};
+use papier::papervm::{MemoryCell, PaperVM, Pos, WriteKind, Writer};
+
+use crate::grid::Grid;
+use crate::render::{collect_papers, draw_inked_sheet, draw_paper, Paper, INK};
+
+pub const COPY: Rgba<u8> = Rgba([30, 90, 200, 255]);
+pub const ARITHMETIC: Rgba<u8> = Rgba([20, 140, 60, 255]);
+pub const RESULT: Rgba<u8> = Rgba([200, 40, 40, 255]);
+pub const ARGUMENT: Rgba<u8> = Rgba([130, 60, 160, 255]);
+
+/// The colour of what an instruction of the kind wrote. `Write` is in plain
+/// ink.
+pub fn colour(kind: WriteKind) -> Rgba<u8> {
+    match kind {
+        WriteKind::Write => INK,
+        WriteKind::Copy => COPY,
+        WriteKind::Arithmetic => ARITHMETIC,
+        WriteKind::Result => RESULT,
+        WriteKind::Argument => ARGUMENT,
+    }
+}
+
+/// Height of a note, in lines
+const NOTE_SIZE: f32 = 0.6;
+
+/// The first written cell of every row and what wrote it. The `_` padding of
+/// numbers is not counted as writing.
+fn row_starts<T: MemoryCell>(vm: &PaperVM<T>) -> Vec<(Pos, Writer)> {
+    let memory = vm.get_memory();
+    let written = |pos: &Pos| {
+        memory
+            .get(pos)
+            .is_some_and(|cell| !cell.read().is_whitespace() && cell.read() != '_')
+    };
+    let mut starts: HashMap<i64, (Pos, Writer)> = HashMap::new();
+    for (&Pos(x, y), &writer) in vm.get_writers() {
+        if !written(&Pos(x, y)) {
+            continue;
+        }
+        let start = starts.entry(y).or_insert((Pos(x, y), writer));
+        if x < start.0 .0 {
+            *start = (Pos(x, y), writer);
+        }
+    }
+    let mut starts: Vec<(Pos, Writer)> = starts.into_values().collect();
+    starts.sort_by_key(|(Pos(_, y), _)| *y);
+    starts
+}
+
+/// Writes the index of the instruction that wrote the start of every row
+/// just in front of it, in the colour of the instruction. Arguments have no
+/// instruction and get no note.
+pub fn draw_notes<T: MemoryCell>(
+    canvas: &mut RgbaImage,
+    vm: &PaperVM<T>,
+    grid: &Grid,
+    font: &impl Font,
+    offset: (i32, i32),
+) {
+    let scale = PxScale::from(NOTE_SIZE * grid.line_height);
+    let ascent = font.as_scaled(scale).ascent();
+    for (Pos(x, y), writer) in row_starts(vm) {
+        let Some(index) = writer.index else {
+            continue;
+        };
+        let note = index.to_string();
+        let (width, _) = text_size(scale, font, &note);
+        // On the baseline of the row, half a cell in front of it
+        let right = grid.text_left(x) - 0.5 * grid.cell_width;
+        let (left, top) = (
+            offset.0 + (right - width as f32).round() as i32,
+            offset.1 + (grid.baseline(y) - ascent).round() as i32,
+        );
+        draw_text_mut(canvas, colour(writer.kind), left, top, scale, font, &note);
+    }
+}
+
+/// Draws a sheet on the paper with every character in the colour of what
+/// wrote it, and with notes in front of the rows if `notes` is set.
+pub fn render_annotated<T: MemoryCell>(
+    vm: &PaperVM<T>,
+    font: &impl Font,
+    scale: PxScale,
+    paper: &Paper,
+    notes: bool,
+) -> RgbaImage {
+    let grid = paper.grid(vm, font, scale);
+    let writers = vm.get_writers();
+
+    let mut image = RgbaImage::new(grid.width().ceil() as u32, grid.height().ceil() as u32);
+    draw_paper(&mut image, &grid, paper, font, (0, 0));
+    draw_inked_sheet(&mut image, vm, &grid, font, scale, (0, 0), |pos| {
+        writers.get(&pos).map_or(INK, |writer| colour(writer.kind))
+    });
+    if notes {
+        draw_notes(&mut image, vm, &grid, font, (0, 0));
+    }
+    image
+}
+
+/// Writes every sheet annotated to `papier_<i>.png` in the directory, in the
+/// order of [`collect_papers`].
+pub fn render_annotated_papers<T: MemoryCell>(
+    root: PaperVM<T>,
+    font: &impl Font,
+    scale: PxScale,
+    paper: &Paper,
+    notes: bool,
+    dir: &Path,
+) -> Result<(), ImageError> {
+    for (i, sheet) in collect_papers(root).into_iter().enumerate() {
+        let image = render_annotated(&sheet, font, scale, paper, notes);
+        image.save(dir.join(format!("papier_{}.png", i)))?;
+    }
+    Ok(())
+}
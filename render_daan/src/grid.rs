@@ -0,0 +1,127 @@
+//! Where the cells of a sheet end up in a picture of it.
+//!
+//! Every `Pos` gets a cell as wide as the widest character of the font and
+//! as high as a line of it, so words line up in columns like they do on the
+//! VM's sheet, whichever font is used.
+
+use ab_glyph::{Font, PxScale, ScaleFont};
+use papier::papervm::{MemoryCell, PaperVM, Pos, Word};
+
+/// The cells of a sheet, from the top left one that is written to the bottom
+/// right one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Grid {
+    /// Top left cell
+    pub origin: Pos,
+    pub columns: i64,
+    pub rows: i64,
+    pub cell_width: f32,
+    pub line_height: f32,
+    /// From the top of a line to the baseline of its text
+    pub ascent: f32,
+    /// From the left of a cell to where its character starts
+    pub indent: f32,
+    /// Around the cells, on all sides
+    pub margin: f32,
+}
+
+/// The smallest and largest written or circled position of a sheet.
+pub fn bounds<T: MemoryCell>(vm: &PaperVM<T>) -> Option<(Pos, Pos)> {
+    let circled = vm
+        .get_circled_all()
+        .into_iter()
+        .flat_map(|Word(Pos(x, y), len)| [Pos(x, y), Pos(x + len.max(1) as i64 - 1, y)]);
+    let positions: Vec<Pos> = vm.get_memory().keys().copied().chain(circled).collect();
+
+    let min_x = positions.iter().map(|pos| pos.0).min()?;
+    let max_x = positions.iter().map(|pos| pos.0).max()?;
+    let min_y = positions.iter().map(|pos| pos.1).min()?;
+    let max_y = positions.iter().map(|pos| pos.1).max()?;
+    Some((Pos(min_x, min_y), Pos(max_x, max_y)))
+}
+
+impl Grid {
+    /// The grid of a sheet drawn in the font at the scale. An empty sheet
+    /// has a single empty cell.
+    pub fn new<T: MemoryCell>(
+        vm: &PaperVM<T>,
+        font: &impl Font,
+        scale: PxScale,
+        margin: f32,
+    ) -> Grid {
+        let (min, max) = bounds(vm).unwrap_or((Pos(0, 0), Pos(0, 0)));
+        Grid::from_bounds(min, max, font, scale, margin)
+    }
+
+    /// The grid from `min` to `max`, for drawing several sheets the same way.
+    pub fn from_bounds(min: Pos, max: Pos, font: &impl Font, scale: PxScale, margin: f32) -> Grid {
+        let font = font.as_scaled(scale);
+        let cell_width = (' '..='~')
+            .map(|c| font.h_advance(font.glyph_id(c)))
+            .fold(0., f32::max);
+
+        Grid {
+            origin: min,
+            columns: max.0 - min.0 + 1,
+            rows: max.1 - min.1 + 1,
+            cell_width,
+            line_height: font.height() + font.line_gap(),
+            ascent: font.ascent(),
+            indent: 0.,
+            margin,
+        }
+    }
+
+    /// The same grid with square cells, as high as a line or as wide as a
+    /// cell if that is more. Characters are written in the middle of them.
+    pub fn squared(self) -> Grid {
+        let side = self.cell_width.max(self.line_height);
+        Grid {
+            cell_width: side,
+            line_height: side,
+            ascent: self.ascent + (side - self.line_height) / 2.,
+            indent: self.indent + (side - self.cell_width) / 2.,
+            ..self
+        }
+    }
+
+    pub fn width(&self) -> f32 {
+        2. * self.margin + self.columns as f32 * self.cell_width
+    }
+
+    pub fn height(&self) -> f32 {
+        2. * self.margin + self.rows as f32 * self.line_height
+    }
+
+    /// Left side of the cells in column `x`
+    pub fn left(&self, x: i64) -> f32 {
+        self.margin + (x - self.origin.0) as f32 * self.cell_width
+    }
+
+    /// Top of the cells in row `y`
+    pub fn top(&self, y: i64) -> f32 {
+        self.margin + (y - self.origin.1) as f32 * self.line_height
+    }
+
+    /// Where the character in column `x` starts
+    pub fn text_left(&self, x: i64) -> f32 {
+        self.left(x) + self.indent
+    }
+
+    pub fn baseline(&self, y: i64) -> f32 {
+        self.top(y) + self.ascent
+    }
+
+    /// Centre and horizontal and vertical radius of an ellipse around the
+    /// cells of the word. It reaches half a line past the ends of the word
+    /// and goes through the corners of its cells.
+    pub fn ellipse(&self, word: Word) -> ((f32, f32), f32, f32) {
+        let Word(Pos(x, y), len) = word;
+        let (width, height) = (len.max(1) as f32 * self.cell_width, self.line_height);
+        let centre = (self.left(x) + width / 2., self.top(y) + height / 2.);
+
+        let rx = (width + height) / 2.;
+        let ry = height / 2. / (1. - (width / 2. / rx).powi(2)).sqrt();
+        (centre, rx, ry)
+    }
+}
@@ -0,0 +1,391 @@
+//! Sheets that look written by hand instead of typeset.
+//!
+//! A [`Hand`] has a few ways to write every character: its fonts and any
+//! scanned glyph images. Which one is used, and how much the character is
+//! moved, turned and how dark its ink is, depends on where it is on the sheet,
+//! so the same sheet is always drawn the same way but no two characters look
+//! alike.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use ab_glyph::{point, Font, FontArc, InvalidFont, PxScale};
+use imageproc::{
+    geometric_transformations::{rotate, Interpolation},
+    image::{self, imageops, GrayImage, ImageError, Luma, Pixel, Rgba, RgbaImage},
+};
+use papier::papervm::{MemoryCell, PaperVM, Pos};
+
+use crate::grid::Grid;
+use crate::render::{collect_papers, draw_circles, draw_paper, overwritten, strike, Paper, INK};
+
+/// How far characters stray from where they would be typeset. Every value is
+/// the most a character is changed, in either direction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Jitter {
+    /// Up or down, in lines
+    pub baseline: f32,
+    /// Left or right, in cells
+    pub shift: f32,
+    /// Turn around the start of the baseline, in radians
+    pub rotation: f32,
+    /// How much lighter the ink can be, from 0 to 1
+    pub ink: f32,
+}
+
+impl Jitter {
+    pub const NONE: Jitter = Jitter {
+        baseline: 0.,
+        shift: 0.,
+        rotation: 0.,
+        ink: 0.,
+    };
+}
+
+impl Default for Jitter {
+    fn default() -> Self {
+        Jitter {
+            baseline: 0.05,
+            shift: 0.06,
+            rotation: 0.07,
+            ink: 0.3,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum HandwritingError {
+    Io(io::Error),
+    Font(PathBuf, InvalidFont),
+    Image(PathBuf, ImageError),
+    /// A glyph image whose name doesn't say which character it is
+    Name(PathBuf),
+}
+
+impl Display for HandwritingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandwritingError::Io(e) => write!(f, "{}", e),
+            HandwritingError::Font(path, e) => write!(f, "{}: {}", path.display(), e),
+            HandwritingError::Image(path, e) => write!(f, "{}: {}", path.display(), e),
+            HandwritingError::Name(path) => write!(
+                f,
+                "{}: expected a name like `a.png`, `a_2.png` or `U+2F_1.png`",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HandwritingError {}
+
+impl From<io::Error> for HandwritingError {
+    fn from(e: io::Error) -> Self {
+        HandwritingError::Io(e)
+    }
+}
+
+/// The character a glyph image is for, from its file name: the character
+/// itself or its code point as `U+2F`, optionally followed by `_` and
+/// anything to tell variants apart.
+pub fn glyph_name(path: &Path) -> Option<char> {
+    let stem = path.file_stem()?.to_str()?;
+    // A `_` on its own is the character, not a separator
+    let name = match stem.rsplit_once('_') {
+        Some((name, _)) if !name.is_empty() => name,
+        _ => stem,
+    };
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => {
+            let hex = name.strip_prefix("U+").or(name.strip_prefix("u+"))?;
+            char::from_u32(u32::from_str_radix(hex, 16).ok()?)
+        }
+    }
+}
+
+pub struct Hand {
+    /// Sets the size of the cells, and is the first way to write every
+    /// character
+    font: FontArc,
+    fonts: Vec<FontArc>,
+    /// Ink of scanned characters, as high as a line
+    images: HashMap<char, Vec<GrayImage>>,
+    pub jitter: Jitter,
+}
+
+impl Hand {
+    pub fn new(font: FontArc) -> Hand {
+        Hand {
+            font,
+            fonts: vec![],
+            images: HashMap::new(),
+            jitter: Jitter::default(),
+        }
+    }
+
+    /// Writes with all of the fonts, the first one sets the size of the
+    /// cells. `None` without fonts.
+    pub fn from_fonts(fonts: Vec<FontArc>) -> Option<Hand> {
+        let mut fonts = fonts.into_iter();
+        let mut hand = Hand::new(fonts.next()?);
+        hand.fonts.extend(fonts);
+        Some(hand)
+    }
+
+    pub fn add_font(&mut self, font: FontArc) {
+        self.fonts.push(font);
+    }
+
+    /// Adds a scanned character, dark ink on a light background or on a
+    /// transparent one. It is scaled to the height of a line, with the
+    /// baseline where the font has it.
+    pub fn add_glyph(&mut self, c: char, image: &RgbaImage) {
+        let ink = GrayImage::from_fn(image.width(), image.height(), |x, y| {
+            let Rgba([r, g, b, a]) = *image.get_pixel(x, y);
+            let light = (r as f32 + g as f32 + b as f32) / 3. / 255.;
+            Luma([((1. - light) * a as f32) as u8])
+        });
+        self.images.entry(c).or_default().push(ink);
+    }
+
+    /// Adds every font (`.ttf`, `.otf`) and glyph image (`.png`, `.jpg`) in
+    /// the directory. Glyph images are named after their character, see
+    /// [`HandwritingError::Name`]. Other files are left alone.
+    pub fn load_dir(&mut self, dir: &Path) -> Result<(), HandwritingError> {
+        let mut paths = fs::read_dir(dir)?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        // Variants are picked by index, so they have to keep it between runs
+        paths.sort();
+
+        for path in paths {
+            let extension = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(|e| e.to_ascii_lowercase());
+            match extension.as_deref() {
+                Some("ttf" | "otf") => {
+                    let font = FontArc::try_from_vec(fs::read(&path)?)
+                        .map_err(|e| HandwritingError::Font(path.clone(), e))?;
+                    self.add_font(font);
+                }
+                Some("png" | "jpg" | "jpeg") => {
+                    let c = glyph_name(&path).ok_or(HandwritingError::Name(path.clone()))?;
+                    let image =
+                        image::open(&path).map_err(|e| HandwritingError::Image(path.clone(), e))?;
+                    self.add_glyph(c, &image.to_rgba8());
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub fn font(&self) -> &FontArc {
+        &self.font
+    }
+
+    /// The ink of every way to write the character, as it would be typeset
+    /// in a cell of the grid: `x` at the left of the cell, the baseline at
+    /// `grid.ascent`. There is room for a cell left and right of it.
+    fn variants(&self, c: char, grid: &Grid, scale: PxScale) -> Vec<GrayImage> {
+        let width = (3. * grid.cell_width).ceil() as u32;
+        let height = grid.line_height.ceil() as u32;
+
+        let mut variants: Vec<GrayImage> = [&self.font]
+            .into_iter()
+            .chain(&self.fonts)
+            .filter(|font| font.glyph_id(c).0 != 0)
+            .filter_map(|font| {
+                let glyph = font
+                    .glyph_id(c)
+                    .with_scale_and_position(scale, point(grid.cell_width, grid.ascent));
+                let outline = font.outline_glyph(glyph)?;
+                let bounds = outline.px_bounds();
+                let mut ink = GrayImage::new(width, height);
+                outline.draw(|x, y, coverage| {
+                    let (x, y) = (
+                        x as i32 + bounds.min.x as i32,
+                        y as i32 + bounds.min.y as i32,
+                    );
+                    if (0..width as i32).contains(&x) && (0..height as i32).contains(&y) {
+                        ink.put_pixel(x as u32, y as u32, Luma([(coverage * 255.) as u8]));
+                    }
+                });
+                Some(ink)
+            })
+            .collect();
+
+        for image in self.images.get(&c).into_iter().flatten() {
+            let scaled_width = image.width() * height / image.height().max(1);
+            let scaled = imageops::resize(
+                image,
+                scaled_width.clamp(1, width),
+                height,
+                imageops::FilterType::Triangle,
+            );
+            let mut ink = GrayImage::new(width, height);
+            imageops::overlay(&mut ink, &scaled, grid.cell_width as i64, 0);
+            variants.push(ink);
+        }
+        variants
+    }
+
+    /// Writes the characters and circles of a sheet on the canvas, with the
+    /// top left of the grid at `offset`.
+    pub fn draw_sheet<T: MemoryCell>(
+        &self,
+        canvas: &mut RgbaImage,
+        vm: &PaperVM<T>,
+        grid: &Grid,
+        scale: PxScale,
+        offset: (i32, i32),
+    ) {
+        let mut variants: HashMap<char, Vec<GrayImage>> = HashMap::new();
+
+        for (&Pos(x, y), cell) in vm.get_memory() {
+            let (crossed, c) = overwritten(cell);
+            // Values written over are half as dark and crossed out
+            for c in crossed {
+                let variants = variants
+                    .entry(c)
+                    .or_insert_with(|| self.variants(c, grid, scale));
+                self.write(canvas, variants, grid, (Pos(x, y), c), offset, 0.5);
+                let left = offset.0 + grid.text_left(x).round() as i32;
+                let top = offset.1 + grid.top(y).round() as i32;
+                strike(canvas, grid, (left, top));
+            }
+            if !c.is_whitespace() {
+                let variants = variants
+                    .entry(c)
+                    .or_insert_with(|| self.variants(c, grid, scale));
+                self.write(canvas, variants, grid, (Pos(x, y), c), offset, 1.);
+            }
+        }
+
+        draw_circles(canvas, vm, grid, scale, offset);
+    }
+
+    /// Writes the character in its cell in one of its variants, at most as
+    /// dark as `darkness`.
+    fn write(
+        &self,
+        canvas: &mut RgbaImage,
+        variants: &[GrayImage],
+        grid: &Grid,
+        (Pos(x, y), c): (Pos, char),
+        offset: (i32, i32),
+        darkness: f32,
+    ) {
+        if variants.is_empty() {
+            return;
+        }
+
+        let mut dice = Dice::new(x, y, c);
+        let ink = &variants[dice.roll() as usize % variants.len()];
+        let baseline = dice.spread(self.jitter.baseline) * grid.line_height;
+        let shift = dice.spread(self.jitter.shift) * grid.cell_width;
+        let rotation = dice.spread(self.jitter.rotation);
+        let darkness = darkness * (1. - dice.unit() * self.jitter.ink);
+
+        let ink = if rotation == 0. {
+            ink.clone()
+        } else {
+            let nib = (grid.cell_width, grid.ascent);
+            rotate(ink, nib, rotation, Interpolation::Bilinear, Luma([0]))
+        };
+        let left = offset.0 + (grid.text_left(x) - grid.cell_width + shift).round() as i32;
+        let top = offset.1 + (grid.top(y) + baseline).round() as i32;
+        blend(canvas, &ink, (left, top), darkness);
+    }
+}
+
+/// Puts ink on the canvas, at `darkness` of the colour of [`INK`].
+fn blend(canvas: &mut RgbaImage, ink: &GrayImage, (left, top): (i32, i32), darkness: f32) {
+    for (x, y, &Luma([coverage])) in ink.enumerate_pixels() {
+        let (cx, cy) = (left + x as i32, top + y as i32);
+        if coverage == 0
+            || cx < 0
+            || cy < 0
+            || cx >= canvas.width() as i32
+            || cy >= canvas.height() as i32
+        {
+            continue;
+        }
+        let alpha = (coverage as f32 * darkness) as u8;
+        let Rgba([r, g, b, _]) = INK;
+        canvas
+            .get_pixel_mut(cx as u32, cy as u32)
+            .blend(&Rgba([r, g, b, alpha]));
+    }
+}
+
+/// Numbers that only depend on where a character is and which one it is,
+/// from SplitMix64.
+pub struct Dice(u64);
+
+impl Dice {
+    /// The dice for character `c` at `(x, y)`
+    pub fn new(x: i64, y: i64, c: char) -> Dice {
+        let seed = (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+            ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+            ^ (c as u64).wrapping_mul(0x1656_67B1_9E37_79F9);
+        Dice(seed)
+    }
+
+    /// Any 64-bit number
+    pub fn roll(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// From 0 to 1
+    pub fn unit(&mut self) -> f32 {
+        (self.roll() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// From `-max` to `max`
+    pub fn spread(&mut self, max: f32) -> f32 {
+        (2. * self.unit() - 1.) * max
+    }
+}
+
+/// Draws a sheet by hand on the paper, like
+/// [`render_paper`](crate::render::render_paper) does in type.
+pub fn render_paper<T: MemoryCell>(
+    vm: &PaperVM<T>,
+    hand: &Hand,
+    scale: PxScale,
+    paper: &Paper,
+) -> RgbaImage {
+    let grid = paper.grid(vm, hand.font(), scale);
+
+    let mut image = RgbaImage::new(grid.width().ceil() as u32, grid.height().ceil() as u32);
+    draw_paper(&mut image, &grid, paper, hand.font(), (0, 0));
+    hand.draw_sheet(&mut image, vm, &grid, scale, (0, 0));
+    image
+}
+
+/// Writes every sheet to `papier_<i>.png` in the directory, in the order of
+/// [`collect_papers`].
+pub fn render_papers<T: MemoryCell>(
+    root: PaperVM<T>,
+    hand: &Hand,
+    scale: PxScale,
+    paper: &Paper,
+    dir: &Path,
+) -> Result<(), ImageError> {
+    for (i, sheet) in collect_papers(root).into_iter().enumerate() {
+        let image = render_paper(&sheet, hand, scale, paper);
+        image.save(dir.join(format!("papier_{}.png", i)))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,8 @@
+pub mod animate;
+pub mod annotate;
+pub mod grid;
+pub mod handwriting;
+pub mod overview;
+pub mod pdf;
+pub mod render;
+pub mod svg;
@@ -1,26 +1,271 @@
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use ab_glyph::{FontArc, PxScale};
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use imageproc::image::Rgba;
 use papier::{
-    convenience::call_static,
-    papervm::{instructions::circle, CharCell, PaperVM, CHARS_PER_FLOAT},
-    programs::{gcd, gcd_with_mod},
+    asm::parse_program,
+    papervm::{CharCell, MemoryCell, OverwritableCell, PaperVM},
+};
+use render_daan::{
+    animate::{self, AnimationFormat, AnimationOptions},
+    annotate,
+    handwriting::{self, Hand},
+    overview,
+    pdf::{self, PdfOptions},
+    render::{self, Lines, Paper, DEFAULT_FONT},
+    svg::{self, SvgOptions},
 };
 
-pub mod render;
+fn cli() -> Command {
+    Command::new("render_daan")
+        .about("Runs a paper program and draws the sheets it was worked out on")
+        .arg(Arg::new("program").required(true))
+        .arg(
+            Arg::new("arg")
+                .long("arg")
+                .action(ArgAction::Append)
+                .help("Written on the sheet before the program starts, like call arguments"),
+        )
+        .arg(
+            Arg::new("cell")
+                .long("cell")
+                .value_parser(["char", "overwritable"])
+                .default_value("char"),
+        )
+        .arg(
+            Arg::new("font")
+                .long("font")
+                .help("TrueType or OpenType font to write with"),
+        )
+        .arg(
+            Arg::new("handwriting")
+                .long("handwriting")
+                .help("Directory of glyph images to write PNG sheets by hand with"),
+        )
+        .arg(
+            Arg::new("scale")
+                .long("scale")
+                .value_parser(value_parser!(f32))
+                .default_value("48")
+                .help("Height of a line of writing, in pixels"),
+        )
+        .arg(
+            Arg::new("paper")
+                .long("paper")
+                .value_parser(parse_colour)
+                .default_value("#ffffff")
+                .help("Colour of the paper, as #rrggbb"),
+        )
+        .arg(
+            Arg::new("lines")
+                .long("lines")
+                .value_parser(["blank", "ruled", "squared"])
+                .default_value("blank")
+                .help("Squared paper has a square for every cell"),
+        )
+        .arg(
+            Arg::new("labels")
+                .long("labels")
+                .action(ArgAction::SetTrue)
+                .help("Write the coordinates of the cells in the margins"),
+        )
+        .arg(
+            Arg::new("margin")
+                .long("margin")
+                .value_parser(value_parser!(f32))
+                .default_value("1")
+                .help("Paper around the writing, in lines"),
+        )
+        .arg(Arg::new("out").long("out").default_value("."))
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .value_parser(["png", "annotated", "svg", "pdf", "tree", "gif", "apng"])
+                .default_value("png")
+                .help(
+                    "Sheets as PNG, as PNG coloured by what wrote them, as SVG or one PDF, \
+                     all sheets as a tree, or an animation",
+                ),
+        )
+        .arg(
+            Arg::new("notes")
+                .long("notes")
+                .action(ArgAction::SetTrue)
+                .help("Note which instruction wrote the start of every row of annotated sheets"),
+        )
+        .arg(
+            Arg::new("every")
+                .long("every")
+                .value_parser(value_parser!(usize))
+                .default_value("4")
+                .help("Number of steps between frames of an animation"),
+        )
+        .arg(
+            Arg::new("max-steps")
+                .long("max-steps")
+                .value_parser(value_parser!(u64)),
+        )
+}
 
-fn main() {
-    let mut vm = PaperVM::<CharCell>::new(call_static(
-        gcd_with_mod(),
-        vec![1123., 127.],
-        CHARS_PER_FLOAT,
-    ));
+fn parse_colour(colour: &str) -> Result<Rgba<u8>, String> {
+    let hex = colour
+        .strip_prefix('#')
+        .filter(|hex| hex.len() == 6 && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+        .ok_or_else(|| format!("{} is not a colour like #rrggbb", colour))?;
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+    Ok(Rgba([channel(0), channel(2), channel(4), 255]))
+}
 
-    while !vm.step().is_finished() {
-        println!("{}", vm.print());
+struct RenderOptions {
+    name: String,
+    args: Vec<String>,
+    font: Vec<u8>,
+    handwriting: Option<PathBuf>,
+    scale: f32,
+    paper: Paper,
+    out: PathBuf,
+    format: String,
+    notes: bool,
+    every: usize,
+    max_steps: Option<u64>,
+}
+
+/// Runs the program to the end and draws it in the format of the options.
+fn render<T: MemoryCell>(program: &str, options: RenderOptions) -> Result<(), Box<dyn Error>> {
+    let mut vm = PaperVM::<T>::new(parse_program(program)?);
+    for arg in &options.args {
+        match arg.parse::<f64>() {
+            Ok(num) => vm.write(&num),
+            Err(_) => vm.write(arg),
+        }
     }
+    let start = vm.clone();
 
-    println!("{}", vm.print());
-    dbg!(vm.result::<f64>().unwrap());
+    let mut steps = 0;
+    loop {
+        if options.max_steps.is_some_and(|max| steps >= max) {
+            return Err(format!("not finished after the step limit of {}", steps).into());
+        }
+        steps += 1;
+        if vm.try_step()?.is_finished() {
+            break;
+        }
+    }
 
-    println!("Finished running");
+    let scale = PxScale::from(options.scale);
+    let font = FontArc::try_from_vec(options.font.clone())?;
+    let out = options.out.as_path();
+    match options.format.as_str() {
+        "png" => match &options.handwriting {
+            Some(dir) => {
+                let mut hand = Hand::new(font);
+                hand.load_dir(dir)?;
+                handwriting::render_papers(vm, &hand, scale, &options.paper, out)?;
+            }
+            None => render::render_papers(vm, &font, scale, &options.paper, out)?,
+        },
+        "annotated" => {
+            annotate::render_annotated_papers(vm, &font, scale, &options.paper, options.notes, out)?
+        }
+        "svg" => {
+            let svg_options = SvgOptions {
+                font: &options.font,
+                scale: options.scale,
+                paper: options.paper,
+            };
+            svg::render_svgs(vm, out, &svg_options)?;
+        }
+        "pdf" => {
+            let pdf_options = PdfOptions {
+                font: &options.font,
+                // Pixels at 96 per inch
+                size: options.scale * 0.75,
+                paper: options.paper,
+            };
+            let path = out.join(format!("{}.pdf", options.name));
+            pdf::render_pdf(&vm, &options.name, &path, &pdf_options)?;
+        }
+        "tree" => {
+            let tree = overview::render_overview(&vm, &font, scale, &options.paper);
+            tree.save(out.join("papier_tree.png"))?;
+        }
+        format => {
+            let (format, path) = match format {
+                "apng" => (AnimationFormat::Apng, "papier.png"),
+                _ => (AnimationFormat::Gif, "papier.gif"),
+            };
+            let animation_options = AnimationOptions {
+                font: &options.font,
+                scale: options.scale,
+                every: options.every,
+                delay: 100,
+                slide: 6,
+                paper: options.paper,
+            };
+            animate::animate(start, &out.join(path), format, &animation_options)?;
+        }
+    }
+    Ok(())
+}
+
+fn render_command(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let path = matches.get_one::<String>("program").unwrap();
+    let program = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let font = match matches.get_one::<String>("font") {
+        Some(font) => fs::read(font).map_err(|e| format!("{}: {}", font, e))?,
+        None => DEFAULT_FONT.to_vec(),
+    };
+    let lines = match matches.get_one::<String>("lines").unwrap().as_str() {
+        "ruled" => Lines::Ruled,
+        "squared" => Lines::Squared,
+        _ => Lines::Blank,
+    };
+
+    let options = RenderOptions {
+        name: Path::new(path)
+            .file_stem()
+            .map_or("papier".into(), |stem| stem.to_string_lossy().into_owned()),
+        args: matches
+            .get_many::<String>("arg")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        font,
+        handwriting: matches.get_one::<String>("handwriting").map(PathBuf::from),
+        scale: *matches.get_one::<f32>("scale").unwrap(),
+        paper: Paper {
+            colour: *matches.get_one::<Rgba<u8>>("paper").unwrap(),
+            lines,
+            margin: *matches.get_one::<f32>("margin").unwrap(),
+            labels: matches.get_flag("labels"),
+        },
+        out: PathBuf::from(matches.get_one::<String>("out").unwrap()),
+        format: matches.get_one::<String>("format").unwrap().clone(),
+        notes: matches.get_flag("notes"),
+        every: *matches.get_one::<usize>("every").unwrap(),
+        max_steps: matches.get_one::<u64>("max-steps").copied(),
+    };
+
+    match matches.get_one::<String>("cell").unwrap().as_str() {
+        "overwritable" => render::<OverwritableCell>(&program, options),
+        _ => render::<CharCell>(&program, options),
+    }
+}
 
-    render::render_papers(vm);
+fn main() -> ExitCode {
+    let matches = cli().get_matches();
+
+    match render_command(&matches) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
 }
@@ -0,0 +1,197 @@
+//! One picture of all sheets of a run, laid out as the tree of calls.
+//!
+//! Every sheet is below the sheet that called it. A blue arrow goes from
+//! where the cursor was on the calling sheet when the call was made to the
+//! called sheet, and a red one from its circled result back to where the
+//! result was copied.
+
+use ab_glyph::{Font, PxScale};
+use imageproc::{
+    drawing::{draw_hollow_rect_mut, draw_line_segment_mut, draw_polygon_mut},
+    image::{Rgba, RgbaImage},
+    point::Point,
+    rect::Rect,
+};
+use papier::papervm::{MemoryCell, PaperVM, Pos};
+
+use crate::grid::Grid;
+use crate::render::{draw_paper, draw_sheet, Paper, DESK, EDGE};
+
+const CALL: Rgba<u8> = Rgba([40, 70, 200, 255]);
+const RESULT: Rgba<u8> = Rgba([200, 40, 40, 255]);
+
+/// A sheet and the sheets it called, with where they go in the picture.
+struct Node<'a, T: MemoryCell> {
+    vm: &'a PaperVM<T>,
+    grid: Grid,
+    /// Top left of the sheet
+    at: (i32, i32),
+    /// Width of the sheet and everything it called
+    width: i32,
+    children: Vec<Node<'a, T>>,
+}
+
+impl<'a, T: MemoryCell> Node<'a, T> {
+    fn new(vm: &'a PaperVM<T>, font: &impl Font, scale: PxScale, paper: &Paper) -> Node<'a, T> {
+        let grid = paper.grid(vm, font, scale);
+        let children: Vec<Node<T>> = vm
+            .finished_papers
+            .iter()
+            .map(|sheet| Node::new(sheet, font, scale, paper))
+            .collect();
+        let gap = scale.y as i32;
+        let below = children.iter().map(|child| child.width + gap).sum::<i32>() - gap;
+        Node {
+            vm,
+            width: (grid.width().ceil() as i32).max(below),
+            grid,
+            at: (0, 0),
+            children,
+        }
+    }
+
+    fn height(&self) -> i32 {
+        self.grid.height().ceil() as i32
+    }
+
+    /// Places the sheet centred over its calls, with its subtree starting at
+    /// `left`. `rows` are the tops of this depth and every one below it.
+    fn place(&mut self, left: i32, rows: &[i32], gap: i32) {
+        self.at = (
+            left + (self.width - self.grid.width().ceil() as i32) / 2,
+            rows[0],
+        );
+        let below = self
+            .children
+            .iter()
+            .map(|child| child.width + gap)
+            .sum::<i32>()
+            - gap;
+        let mut left = left + (self.width - below) / 2;
+        for child in &mut self.children {
+            child.place(left, &rows[1..], gap);
+            left += child.width + gap;
+        }
+    }
+
+    /// Height of the highest sheet at every depth, from this one down.
+    fn row_heights(&self, heights: &mut Vec<i32>, depth: usize) {
+        if heights.len() <= depth {
+            heights.push(0);
+        }
+        heights[depth] = heights[depth].max(self.height());
+        for child in &self.children {
+            child.row_heights(heights, depth + 1);
+        }
+    }
+
+    /// Centre of a cell on the sheet, in the picture.
+    fn cell(&self, Pos(x, y): Pos) -> (f32, f32) {
+        (
+            self.at.0 as f32 + self.grid.left(x) + self.grid.cell_width / 2.,
+            self.at.1 as f32 + self.grid.top(y) + self.grid.line_height / 2.,
+        )
+    }
+
+    fn draw_sheets(&self, canvas: &mut RgbaImage, font: &impl Font, scale: PxScale, paper: &Paper) {
+        draw_paper(canvas, &self.grid, paper, font, self.at);
+        let rect = Rect::at(self.at.0, self.at.1)
+            .of_size(self.grid.width().ceil() as u32, self.height() as u32);
+        draw_hollow_rect_mut(canvas, rect, EDGE);
+        draw_sheet(canvas, self.vm, &self.grid, font, scale, self.at);
+        for child in &self.children {
+            child.draw_sheets(canvas, font, scale, paper);
+        }
+    }
+
+    fn draw_arrows(&self, canvas: &mut RgbaImage, size: f32) {
+        for child in &self.children {
+            let Some(call_site) = &child.vm.call_site else {
+                continue;
+            };
+            let top = (
+                child.at.0 as f32 + child.grid.width() / 2.,
+                child.at.1 as f32,
+            );
+            draw_arrow(canvas, self.cell(call_site.cursor), top, CALL, size);
+
+            for (word, result) in child
+                .vm
+                .get_circled_all()
+                .into_iter()
+                .zip(&call_site.results)
+            {
+                let ((x, y), _, ry) = child.grid.ellipse(word);
+                let from = (child.at.0 as f32 + x, child.at.1 as f32 + y - ry);
+                let ((x, y), _, ry) = self.grid.ellipse(*result);
+                let to = (self.at.0 as f32 + x, self.at.1 as f32 + y + ry / 2.);
+                draw_arrow(canvas, from, to, RESULT, size);
+            }
+            child.draw_arrows(canvas, size);
+        }
+    }
+}
+
+/// A line with a head at `to`, `size` long.
+fn draw_arrow(
+    canvas: &mut RgbaImage,
+    from: (f32, f32),
+    to: (f32, f32),
+    colour: Rgba<u8>,
+    size: f32,
+) {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let length = (dx * dx + dy * dy).sqrt();
+    if length < 1. {
+        return;
+    }
+    let (ux, uy) = (dx / length, dy / length);
+    let base = (to.0 - ux * size, to.1 - uy * size);
+
+    for d in [-1., 0., 1.] {
+        draw_line_segment_mut(
+            canvas,
+            (from.0 - uy * d, from.1 + ux * d),
+            (base.0 - uy * d, base.1 + ux * d),
+            colour,
+        );
+    }
+    let point = |x: f32, y: f32| Point::new(x.round() as i32, y.round() as i32);
+    draw_polygon_mut(
+        canvas,
+        &[
+            point(to.0, to.1),
+            point(base.0 - uy * size / 2., base.1 + ux * size / 2.),
+            point(base.0 + uy * size / 2., base.1 - ux * size / 2.),
+        ],
+        colour,
+    );
+}
+
+/// Draws all sheets of the run started from `root` as a tree, with arrows
+/// for the calls and their results.
+pub fn render_overview<T: MemoryCell>(
+    root: &PaperVM<T>,
+    font: &impl Font,
+    scale: PxScale,
+    paper: &Paper,
+) -> RgbaImage {
+    let gap = scale.y as i32;
+    let mut tree = Node::new(root, font, scale, paper);
+
+    // Room between depths for the arrows to be seen
+    let mut heights = vec![];
+    tree.row_heights(&mut heights, 0);
+    let mut rows = vec![gap];
+    for height in &heights {
+        rows.push(rows.last().unwrap() + height + 3 * gap);
+    }
+    tree.place(gap, &rows, gap);
+
+    let width = (tree.width + 2 * gap) as u32;
+    let height = (rows[heights.len()] - 2 * gap) as u32;
+    let mut canvas = RgbaImage::from_pixel(width, height, DESK);
+    tree.draw_sheets(&mut canvas, font, scale, paper);
+    tree.draw_arrows(&mut canvas, scale.y / 3.);
+    canvas
+}
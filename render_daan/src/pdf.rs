@@ -0,0 +1,359 @@
+//! All sheets of a run in one PDF, a page per sheet.
+//!
+//! The pages are in call order, the same order as [`collect_papers`], after
+//! a table of contents that shows which sheet called which. Every sheet is
+//! scaled to fit an A4 page and keeps its writing as text in the embedded
+//! font.
+//!
+//! [`collect_papers`]: crate::render::collect_papers
+
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+use ab_glyph::{Font, FontRef, InvalidFont, PxScale};
+use imageproc::image::Rgba;
+use papier::papervm::{MemoryCell, PaperVM, Pos};
+use printpdf::{
+    BuiltinFont, Color, IndirectFontRef, Line, Mm, PdfDocument, PdfLayerReference, Point, Pt, Rect,
+    Rgb,
+};
+
+use crate::grid::Grid;
+use crate::render::{Paper, INK, LABEL, LABEL_SIZE, RULE};
+
+const PAGE_WIDTH: f32 = 595.;
+const PAGE_HEIGHT: f32 = 842.;
+/// Around the header and the sheet, in points
+const BORDER: f32 = 40.;
+const HEADER_SIZE: f32 = 11.;
+const CONTENTS_SIZE: f32 = 10.;
+
+pub struct PdfOptions<'a> {
+    /// TrueType or OpenType font to write the sheets with
+    pub font: &'a [u8],
+    /// Largest font size of the sheets, in points. Sheets that don't fit on
+    /// a page are written smaller.
+    pub size: f32,
+    pub paper: Paper,
+}
+
+#[derive(Debug)]
+pub enum PdfError {
+    Font(InvalidFont),
+    Pdf(printpdf::Error),
+    Io(io::Error),
+}
+
+impl Display for PdfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PdfError::Font(e) => write!(f, "invalid font: {}", e),
+            PdfError::Pdf(e) => write!(f, "could not write PDF: {}", e),
+            PdfError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for PdfError {}
+
+impl From<InvalidFont> for PdfError {
+    fn from(e: InvalidFont) -> Self {
+        PdfError::Font(e)
+    }
+}
+
+impl From<printpdf::Error> for PdfError {
+    fn from(e: printpdf::Error) -> Self {
+        PdfError::Pdf(e)
+    }
+}
+
+impl From<io::Error> for PdfError {
+    fn from(e: io::Error) -> Self {
+        PdfError::Io(e)
+    }
+}
+
+/// Every sheet with its call depth, in call order.
+fn call_tree<'a, T: MemoryCell>(
+    vm: &'a PaperVM<T>,
+    depth: usize,
+    sheets: &mut Vec<(usize, &'a PaperVM<T>)>,
+) {
+    sheets.push((depth, vm));
+    for paper in &vm.finished_papers {
+        call_tree(paper, depth + 1, sheets);
+    }
+}
+
+/// The circled words of a sheet, without the blanks and `_` padding around
+/// them.
+fn circled_text<T: MemoryCell>(vm: &PaperVM<T>) -> String {
+    let memory = vm.get_memory();
+    let words: Vec<String> = vm
+        .get_circled_all()
+        .into_iter()
+        .map(|word| {
+            let Pos(x, y) = word.0;
+            let text: String = (0..word.1 as i64)
+                .map(|i| memory.get(&Pos(x + i, y)).map_or(' ', |cell| cell.read()))
+                .collect();
+            text.replace('_', " ").trim().to_string()
+        })
+        .collect();
+    words.join(", ")
+}
+
+fn point(x: f32, y: f32) -> Point {
+    Point::new(Mm::from(Pt(x)), Mm::from(Pt(PAGE_HEIGHT - y)))
+}
+
+fn colour(Rgba([r, g, b, _]): Rgba<u8>) -> Color {
+    Color::Rgb(Rgb::new(
+        r as f32 / 255.,
+        g as f32 / 255.,
+        b as f32 / 255.,
+        None,
+    ))
+}
+
+/// Draws the paper of a sheet with the grid, with its top left at `x`, `y`
+/// points from the top left of the page. Coordinates are written in `font`,
+/// which is measured with `glyphs`.
+fn paper(
+    layer: &PdfLayerReference,
+    paper: &Paper,
+    grid: &Grid,
+    (font, glyphs): (&IndirectFontRef, &FontRef),
+    (x, y): (f32, f32),
+) {
+    let (width, height) = (grid.width(), grid.height());
+    let (low, high) = (point(x, y + height), point(x + width, y));
+    layer.set_fill_color(colour(paper.colour));
+    layer.add_rect(Rect::new(
+        low.x.into(),
+        low.y.into(),
+        high.x.into(),
+        high.y.into(),
+    ));
+
+    layer.set_outline_color(colour(RULE));
+    layer.set_outline_thickness(0.5);
+    let (rows, columns) = paper.rules(grid);
+    let rows = rows
+        .into_iter()
+        .map(|row| [(x, y + row), (x + width, y + row)]);
+    let columns = columns
+        .into_iter()
+        .map(|column| [(x + column, y), (x + column, y + height)]);
+    for [from, to] in rows.chain(columns) {
+        layer.add_line(Line {
+            points: vec![(point(from.0, from.1), false), (point(to.0, to.1), false)],
+            is_closed: false,
+        });
+    }
+
+    // Font sizes are for the em square, the label is as high as the font
+    let em = glyphs.units_per_em().unwrap_or(1000.);
+    let size = LABEL_SIZE * grid.line_height * em / glyphs.height_unscaled();
+    layer.set_fill_color(colour(LABEL));
+    for (label, (cx, cy)) in paper.labels(grid) {
+        let width: f32 = label
+            .chars()
+            .map(|c| glyphs.h_advance_unscaled(glyphs.glyph_id(c)) * size / em)
+            .sum();
+        let baseline = cy + LABEL_SIZE * grid.line_height * 0.35;
+        text(
+            layer,
+            &label,
+            size,
+            (x + cx - width / 2., y + baseline),
+            font,
+        );
+    }
+
+    layer.set_fill_color(colour(INK));
+    layer.set_outline_color(colour(INK));
+}
+
+/// Writes at `x`, `y` points from the top left of the page.
+fn text(
+    layer: &PdfLayerReference,
+    text: &str,
+    size: f32,
+    (x, y): (f32, f32),
+    font: &IndirectFontRef,
+) {
+    let (x, y) = (Mm::from(Pt(x)), Mm::from(Pt(PAGE_HEIGHT - y)));
+    layer.use_text(text, size, x, y, font);
+}
+
+/// Four Bézier curves close enough to an ellipse.
+fn ellipse((cx, cy): (f32, f32), rx: f32, ry: f32) -> Line {
+    const K: f32 = 0.552_284_8;
+    let points = [
+        (cx + rx, cy, true),
+        (cx + rx, cy + K * ry, true),
+        (cx + K * rx, cy + ry, false),
+        (cx, cy + ry, true),
+        (cx - K * rx, cy + ry, true),
+        (cx - rx, cy + K * ry, false),
+        (cx - rx, cy, true),
+        (cx - rx, cy - K * ry, true),
+        (cx - K * rx, cy - ry, false),
+        (cx, cy - ry, true),
+        (cx + K * rx, cy - ry, true),
+        (cx + rx, cy - K * ry, false),
+        (cx + rx, cy, false),
+    ];
+    Line {
+        points: points
+            .into_iter()
+            .map(|(x, y, handle)| (point(x, y), handle))
+            .collect(),
+        is_closed: true,
+    }
+}
+
+/// The largest font size up to `size` at which the sheet fits in `width` by
+/// `height` points, with its grid at that size on the paper.
+fn fit<T: MemoryCell>(
+    vm: &PaperVM<T>,
+    font: &FontRef,
+    size: f32,
+    paper: &Paper,
+    width: f32,
+    height: f32,
+) -> (f32, Grid) {
+    // Font sizes are for the em square, ab_glyph scales the whole height
+    let px = |size: f32| {
+        PxScale::from(size * font.height_unscaled() / font.units_per_em().unwrap_or(1000.))
+    };
+    let grid = paper.fit(Grid::new(vm, font, px(size), paper.margin * size));
+    let shrink = (width / grid.width()).min(height / grid.height()).min(1.);
+    if shrink < 1. {
+        let size = size * shrink;
+        let grid = Grid::new(vm, font, px(size), paper.margin * size);
+        (size, paper.fit(grid))
+    } else {
+        (size, grid)
+    }
+}
+
+/// Writes every sheet of the run, started from `root`, to a PDF at the path.
+/// `name` is used for the title and the header of every page.
+pub fn render_pdf<T: MemoryCell>(
+    root: &PaperVM<T>,
+    name: &str,
+    path: &Path,
+    options: &PdfOptions,
+) -> Result<(), PdfError> {
+    let glyphs = FontRef::try_from_slice(options.font)?;
+    let mut sheets = vec![];
+    call_tree(root, 0, &mut sheets);
+
+    let (doc, page, layer) = PdfDocument::new(
+        name,
+        Mm::from(Pt(PAGE_WIDTH)),
+        Mm::from(Pt(PAGE_HEIGHT)),
+        "contents",
+    );
+    let header = doc.add_builtin_font(BuiltinFont::Helvetica)?;
+    let bold = doc.add_builtin_font(BuiltinFont::HelveticaBold)?;
+    let font = doc.add_external_font(options.font)?;
+
+    // Table of contents, on as many pages as it needs
+    let line = CONTENTS_SIZE * 1.6;
+    let first = BORDER + 2.5 * line;
+    let per_page = ((PAGE_HEIGHT - first - BORDER) / line) as usize;
+    let contents_pages = sheets.len().div_ceil(per_page);
+    let mut contents = doc.get_page(page).get_layer(layer);
+    doc.add_bookmark("Contents", page);
+    text(&contents, name, 16., (BORDER, BORDER + 16.), &bold);
+    for (i, &(depth, sheet)) in sheets.iter().enumerate() {
+        if i > 0 && i % per_page == 0 {
+            let (page, layer) = doc.add_page(
+                Mm::from(Pt(PAGE_WIDTH)),
+                Mm::from(Pt(PAGE_HEIGHT)),
+                "contents",
+            );
+            contents = doc.get_page(page).get_layer(layer);
+        }
+        let y = first + (i % per_page) as f32 * line;
+        let title = if depth == 0 {
+            name.to_string()
+        } else {
+            format!("call {}", i)
+        };
+        let result = circled_text(sheet);
+        let entry = if result.is_empty() {
+            title
+        } else {
+            format!("{}  =  {}", title, result)
+        };
+        let indent = BORDER + depth as f32 * 2. * CONTENTS_SIZE;
+        text(&contents, &entry, CONTENTS_SIZE, (indent, y), &header);
+        let number = (contents_pages + i + 1).to_string();
+        text(
+            &contents,
+            &number,
+            CONTENTS_SIZE,
+            (PAGE_WIDTH - BORDER - 2. * CONTENTS_SIZE, y),
+            &header,
+        );
+    }
+
+    for (i, &(depth, sheet)) in sheets.iter().enumerate() {
+        let (page, layer) =
+            doc.add_page(Mm::from(Pt(PAGE_WIDTH)), Mm::from(Pt(PAGE_HEIGHT)), "sheet");
+        let layer = doc.get_page(page).get_layer(layer);
+        let title = if depth == 0 {
+            name.to_string()
+        } else {
+            format!("{}: call {}", name, i)
+        };
+        doc.add_bookmark(title.clone(), page);
+        let heading = format!(
+            "{}    (call depth {}, page {})",
+            title,
+            depth,
+            contents_pages + i + 1
+        );
+        text(&layer, &heading, HEADER_SIZE, (BORDER, BORDER), &header);
+
+        let top = BORDER + 2. * HEADER_SIZE;
+        let (size, grid) = fit(
+            sheet,
+            &glyphs,
+            options.size,
+            &options.paper,
+            PAGE_WIDTH - 2. * BORDER,
+            PAGE_HEIGHT - top - BORDER,
+        );
+        paper(
+            &layer,
+            &options.paper,
+            &grid,
+            (&font, &glyphs),
+            (BORDER, top),
+        );
+        for (&Pos(x, y), cell) in sheet.get_memory() {
+            let c = cell.read();
+            if !c.is_whitespace() {
+                let at = (BORDER + grid.text_left(x), top + grid.baseline(y));
+                text(&layer, &c.to_string(), size, at, &font);
+            }
+        }
+
+        layer.set_outline_thickness(size / 16.);
+        for word in sheet.get_circled_all() {
+            let ((cx, cy), rx, ry) = grid.ellipse(word);
+            layer.add_line(ellipse((BORDER + cx, top + cy), rx, ry));
+        }
+    }
+
+    doc.save(&mut BufWriter::new(File::create(path)?))?;
+    Ok(())
+}
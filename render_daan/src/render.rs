@@ -1,15 +1,184 @@
-use std::process::Command;
+use std::path::Path;
 
-use ab_glyph::{FontRef, PxScale};
+use ab_glyph::{Font, PxScale};
 use imageproc::{
-    drawing::{draw_hollow_circle, draw_hollow_circle_mut, draw_text_mut, text_size},
-    image::{GenericImageView, ImageBuffer, Rgb, RgbImage, Rgba, RgbaImage},
+    drawing::{
+        draw_filled_rect_mut, draw_hollow_ellipse_mut, draw_line_segment_mut, draw_text_mut,
+        text_size,
+    },
+    image::{ImageError, Rgba, RgbaImage},
+    rect::Rect,
 };
 use papier::papervm::{MemoryCell, PaperVM, Pos};
 
-struct FinishedPaper {
-    image: ImageBuffer<Rgba<u8>, Vec<u8>>,
-    answer: (u64, u64, u64, u64),
+use crate::grid::Grid;
+
+/// The font sheets are written in when no other is given
+pub const DEFAULT_FONT: &[u8] = include_bytes!("../JH2TRIAL.ttf");
+
+pub const INK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+/// Under the sheets when more than one is drawn
+pub const DESK: Rgba<u8> = Rgba([214, 208, 196, 255]);
+pub const EDGE: Rgba<u8> = Rgba([150, 150, 150, 255]);
+/// Lines printed on the paper
+pub const RULE: Rgba<u8> = Rgba([170, 195, 225, 255]);
+/// Coordinates in the margins
+pub const LABEL: Rgba<u8> = Rgba([110, 140, 190, 255]);
+/// Height of a coordinate label, in lines
+pub const LABEL_SIZE: f32 = 0.4;
+/// Ink of values that were written over
+pub const CROSSED: Rgba<u8> = Rgba([130, 130, 130, 255]);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lines {
+    Blank,
+    /// A line under every row of cells
+    Ruled,
+    /// Lines around every cell, which are made square so every `Pos` gets
+    /// one square
+    Squared,
+}
+
+/// What the sheets are written on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Paper {
+    pub colour: Rgba<u8>,
+    pub lines: Lines,
+    /// Around the writing, in lines
+    pub margin: f32,
+    /// Whether the coordinates of the cells are written in the margins
+    pub labels: bool,
+}
+
+impl Default for Paper {
+    fn default() -> Self {
+        Paper {
+            colour: Rgba([255, 255, 255, 255]),
+            lines: Lines::Blank,
+            margin: 1.,
+            labels: false,
+        }
+    }
+}
+
+impl Paper {
+    /// The grid of a sheet on this paper.
+    pub fn grid<T: MemoryCell>(&self, vm: &PaperVM<T>, font: &impl Font, scale: PxScale) -> Grid {
+        self.fit(Grid::new(vm, font, scale, self.margin * scale.y))
+    }
+
+    /// The grid as it is on this paper: with square cells on squared paper.
+    pub fn fit(&self, grid: Grid) -> Grid {
+        match self.lines {
+            Lines::Squared => grid.squared(),
+            _ => grid,
+        }
+    }
+
+    /// The coordinates to write in the margins and the centres of where they
+    /// go: the `x` of the columns above them and the `y` of the rows left of
+    /// them. Columns too narrow for their label are only labelled every few.
+    pub fn labels(&self, grid: &Grid) -> Vec<(String, (f32, f32))> {
+        if !self.labels {
+            return vec![];
+        }
+        let Pos(left, top) = grid.origin;
+        let (right, bottom) = (left + grid.columns, top + grid.rows);
+
+        // Digits are about half as wide as they are high
+        let digits = left.abs().max(right.abs()).to_string().len() + 1;
+        let width = (digits as f32 + 1.) * LABEL_SIZE * grid.line_height / 2.;
+        let every = [1, 2, 5, 10, 20, 50, 100]
+            .into_iter()
+            .find(|&every| every as f32 * grid.cell_width >= width)
+            .unwrap_or(100);
+
+        let columns = (left..right).filter(|x| x % every == 0).map(|x| {
+            let centre = grid.left(x) + grid.cell_width / 2.;
+            (x.to_string(), (centre, grid.margin / 2.))
+        });
+        let rows = (top..bottom).map(|y| {
+            let centre = grid.top(y) + grid.line_height / 2.;
+            (y.to_string(), (grid.margin / 2., centre))
+        });
+        columns.chain(rows).collect()
+    }
+
+    /// Where the lines of the paper are on a sheet with the grid: the
+    /// heights of the horizontal ones and the offsets of the vertical ones.
+    /// They go on over the margin, so they line up with the cells.
+    pub fn rules(&self, grid: &Grid) -> (Vec<f32>, Vec<f32>) {
+        let every = |step: f32, end: f32| {
+            let first = grid.margin - (grid.margin / step).floor() * step;
+            (0..)
+                .map(move |i| first + i as f32 * step)
+                .take_while(move |&at| at <= end)
+                .collect::<Vec<f32>>()
+        };
+        match self.lines {
+            Lines::Blank => (vec![], vec![]),
+            Lines::Ruled => (every(grid.line_height, grid.height()), vec![]),
+            Lines::Squared => (
+                every(grid.line_height, grid.height()),
+                every(grid.cell_width, grid.width()),
+            ),
+        }
+    }
+}
+
+/// Draws the paper of a sheet with the grid, with its top left at `offset`.
+/// Coordinates are written in the font.
+pub fn draw_paper(
+    canvas: &mut RgbaImage,
+    grid: &Grid,
+    paper: &Paper,
+    font: &impl Font,
+    offset: (i32, i32),
+) {
+    let (width, height) = (grid.width(), grid.height());
+    let rect = Rect::at(offset.0, offset.1).of_size(width.ceil() as u32, height.ceil() as u32);
+    draw_filled_rect_mut(canvas, rect, paper.colour);
+
+    let (x, y) = (offset.0 as f32, offset.1 as f32);
+    let (rows, columns) = paper.rules(grid);
+    for row in rows {
+        draw_line_segment_mut(canvas, (x, y + row), (x + width, y + row), RULE);
+    }
+    for column in columns {
+        draw_line_segment_mut(canvas, (x + column, y), (x + column, y + height), RULE);
+    }
+
+    let scale = PxScale::from(LABEL_SIZE * grid.line_height);
+    for (label, (cx, cy)) in paper.labels(grid) {
+        let (w, h) = text_size(scale, font, &label);
+        let (left, top) = (x + cx - w as f32 / 2., y + cy - h as f32 / 2.);
+        draw_text_mut(canvas, LABEL, left as i32, top as i32, scale, font, &label);
+    }
+}
+
+/// The values written over in a cell that are still worth showing, oldest
+/// first, and the value on top. Blanks and `_` padding leave nothing to
+/// cross out, nor does writing the value that ended up on top.
+pub fn overwritten<T: MemoryCell>(cell: &T) -> (Vec<char>, char) {
+    let mut history = cell.history();
+    let top = history.pop().unwrap_or(' ');
+    history.retain(|&c| !c.is_whitespace() && c != '_' && c != top);
+    history.dedup();
+    (history, top)
+}
+
+/// A line through the cell with its top left at `(left, top)`, rising a
+/// little like a quick stroke of the pen.
+pub fn strike(canvas: &mut RgbaImage, grid: &Grid, (left, top): (i32, i32)) {
+    let middle = top as f32 + grid.ascent * 0.6;
+    let width = grid.cell_width - 2. * grid.indent;
+    let (start, end) = (
+        (left as f32 - 0.1 * width, middle + 0.08 * grid.line_height),
+        (left as f32 + 1.1 * width, middle - 0.08 * grid.line_height),
+    );
+    for d in [-0.5, 0.5] {
+        draw_line_segment_mut(canvas, (start.0, start.1 + d), (end.0, end.1 + d), INK);
+    }
 }
 
 pub fn collect_papers<T: MemoryCell>(root: PaperVM<T>) -> Vec<PaperVM<T>> {
@@ -20,70 +189,105 @@ pub fn collect_papers<T: MemoryCell>(root: PaperVM<T>) -> Vec<PaperVM<T>> {
     papers
 }
 
-pub fn render_papers<T: MemoryCell>(root: PaperVM<T>) {
-    for (i, papier) in collect_papers(root).into_iter().enumerate() {
-        let paper = render_paper(&papier);
-        paper.save(format!("papier_{}.png", i)).unwrap();
+/// Writes every sheet to `papier_<i>.png` in the directory, in the order of
+/// [`collect_papers`].
+pub fn render_papers<T: MemoryCell>(
+    root: PaperVM<T>,
+    font: &impl Font,
+    scale: PxScale,
+    paper: &Paper,
+    dir: &Path,
+) -> Result<(), ImageError> {
+    for (i, sheet) in collect_papers(root).into_iter().enumerate() {
+        let image = render_paper(&sheet, font, scale, paper);
+        image.save(dir.join(format!("papier_{}.png", i)))?;
     }
+    Ok(())
 }
 
-fn render_paper<T: MemoryCell>(vm: &PaperVM<T>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
-    let memory = vm.get_memory();
-    let mut lines = vec![];
-
-    for y in 0..100 {
-        let mut line = vec![];
-        for x in 0..75 {
-            let ch = memory
-                .get(&Pos(x, y))
-                .map(|c| c.read().to_string())
-                .unwrap_or(" ".to_string());
-            line.push(ch);
-        }
-        lines.push(line.concat().to_string());
-    }
-
-    let circled = vm.get_circled().unwrap();
-
-    let mut paper = RgbaImage::new(2000, 2000);
-    paper.fill(255);
-    let font = FontRef::try_from_slice(include_bytes!("../JH2TRIAL.ttf")).unwrap();
+/// Draws a sheet on the paper. Every character is drawn in its own cell of
+/// the [`Grid`] and the circled words get an ellipse around them. A sheet
+/// without a circle, like one that is still running, is drawn without one.
+pub fn render_paper<T: MemoryCell>(
+    vm: &PaperVM<T>,
+    font: &impl Font,
+    scale: PxScale,
+    paper: &Paper,
+) -> RgbaImage {
+    let grid = paper.grid(vm, font, scale);
 
-    let text = lines.join("\n");
+    let mut image = RgbaImage::new(grid.width().ceil() as u32, grid.height().ceil() as u32);
+    draw_paper(&mut image, &grid, paper, font, (0, 0));
+    draw_sheet(&mut image, vm, &grid, font, scale, (0, 0));
+    image
+}
 
-    let mut w = 0;
-    let mut h = 20;
+/// Draws the writing and circles of a sheet on the canvas, with the top left
+/// of the grid at `offset`.
+pub fn draw_sheet<T: MemoryCell>(
+    canvas: &mut RgbaImage,
+    vm: &PaperVM<T>,
+    grid: &Grid,
+    font: &impl Font,
+    scale: PxScale,
+    offset: (i32, i32),
+) {
+    draw_inked_sheet(canvas, vm, grid, font, scale, offset, |_| INK);
+}
 
-    for (i, line) in lines.into_iter().enumerate() {
-        draw_text_mut(
-            &mut paper,
-            Rgba([0u8, 0u8, 0u8, 255u8]),
-            20,
-            h as i32,
-            PxScale::from(48.0),
-            &font,
-            &line,
+/// Like [`draw_sheet`], with what is on top of every cell written in the
+/// colour `ink` gives for its `Pos`.
+pub fn draw_inked_sheet<T: MemoryCell>(
+    canvas: &mut RgbaImage,
+    vm: &PaperVM<T>,
+    grid: &Grid,
+    font: &impl Font,
+    scale: PxScale,
+    offset: (i32, i32),
+    ink: impl Fn(Pos) -> Rgba<u8>,
+) {
+    for (&Pos(x, y), cell) in vm.get_memory() {
+        let (left, top) = (
+            offset.0 + grid.text_left(x).round() as i32,
+            offset.1 + grid.top(y).round() as i32,
         );
-        let (next_w, next_h) = text_size(PxScale::from(48.0), &font, &line);
-        w = w.max(next_w);
-        h += next_h + 6;
-
-        if i == circled.0 .1 as usize {
-            println!("w,h: {next_w} {h}");
-            println!("CIRCLE: {circled}");
-            let circle_x = circled.0 .0 as i32 * 12 + circled.1 as i32;
-            let circle_y = h as i32;
-            println!("result: {circle_x} {circle_y}");
-            draw_hollow_circle_mut(
-                &mut paper,
-                (circle_x, circle_y),
-                circled.1 as i32 * 3,
-                Rgba([0u8, 0u8, 0u8, 255u8]),
-            );
+        let (crossed, c) = overwritten(cell);
+        for c in crossed {
+            draw_text_mut(canvas, CROSSED, left, top, scale, font, &c.to_string());
+            strike(canvas, grid, (left, top));
+        }
+        if !c.is_whitespace() {
+            let ink = ink(Pos(x, y));
+            draw_text_mut(canvas, ink, left, top, scale, font, &c.to_string());
         }
     }
 
-    println!("Text size: {}x{}", w, h);
+    draw_circles(canvas, vm, grid, scale, offset);
+}
 
-    paper.view(0, 0, 20 + w, 20 + h + 200).to_image()
+/// Draws ellipses around the circled words of a sheet, with the top left of
+/// the grid at `offset`.
+pub fn draw_circles<T: MemoryCell>(
+    canvas: &mut RgbaImage,
+    vm: &PaperVM<T>,
+    grid: &Grid,
+    scale: PxScale,
+    offset: (i32, i32),
+) {
+    // Drawn a few pixels apart to make the pen as thick as the letters
+    let thickness = (scale.y / 16.).round().max(1.) as i32;
+    for word in vm.get_circled_all() {
+        let ((cx, cy), rx, ry) = grid.ellipse(word);
+        let centre = (offset.0 + cx.round() as i32, offset.1 + cy.round() as i32);
+        for i in 0..thickness {
+            let d = i - thickness / 2;
+            draw_hollow_ellipse_mut(
+                canvas,
+                centre,
+                rx.round() as i32 + d,
+                ry.round() as i32 + d,
+                INK,
+            );
+        }
+    }
 }
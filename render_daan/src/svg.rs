@@ -0,0 +1,162 @@
+//! Sheets as SVG, which can be scaled and printed at any size.
+//!
+//! The font is embedded in the file, so it looks the same without the font
+//! installed. Every character is a `<text>` of its own in its cell of the
+//! [`Grid`], with the `Pos` of the cell in `data-x` and `data-y`. The grid is
+//! also described in the `<metadata>`, so tools can find what is drawn where.
+//!
+//! [`Grid`]: crate::grid::Grid
+
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use ab_glyph::{FontRef, InvalidFont, PxScale};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use imageproc::image::Rgba;
+use papier::papervm::{MemoryCell, PaperVM, Pos};
+
+use crate::render::{collect_papers, Paper, LABEL, LABEL_SIZE, RULE};
+
+pub struct SvgOptions<'a> {
+    /// TrueType or OpenType font to write with
+    pub font: &'a [u8],
+    pub scale: f32,
+    pub paper: Paper,
+}
+
+fn hex(Rgba([r, g, b, _]): Rgba<u8>) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+fn escape(c: char) -> String {
+    match c {
+        '&' => "&amp;".into(),
+        '<' => "&lt;".into(),
+        '>' => "&gt;".into(),
+        c => c.into(),
+    }
+}
+
+/// Draws a sheet as an SVG document.
+pub fn render_svg<T: MemoryCell>(
+    vm: &PaperVM<T>,
+    options: &SvgOptions,
+) -> Result<String, InvalidFont> {
+    let font = FontRef::try_from_slice(options.font)?;
+    let grid = options.paper.grid(vm, &font, PxScale::from(options.scale));
+    let memory = vm.get_memory();
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+        w = grid.width(),
+        h = grid.height(),
+    );
+    let _ = writeln!(
+        svg,
+        r#"<metadata><grid xmlns="urn:papier" x="{}" y="{}" columns="{}" rows="{}" cell-width="{}" line-height="{}" margin="{}"/></metadata>"#,
+        grid.origin.0,
+        grid.origin.1,
+        grid.columns,
+        grid.rows,
+        grid.cell_width,
+        grid.line_height,
+        grid.margin,
+    );
+    let _ = writeln!(
+        svg,
+        r#"<style>@font-face {{ font-family: "papier"; src: url("data:font/ttf;base64,{}"); }} text {{ font-family: "papier"; font-size: {}px; }}</style>"#,
+        STANDARD.encode(options.font),
+        options.scale,
+    );
+    let _ = writeln!(
+        svg,
+        r#"<rect width="100%" height="100%" fill="{}"/>"#,
+        hex(options.paper.colour)
+    );
+    let (rows, columns) = options.paper.rules(&grid);
+    for row in rows {
+        let _ = writeln!(
+            svg,
+            r#"<line x1="0" y1="{y}" x2="{}" y2="{y}" stroke="{}"/>"#,
+            grid.width(),
+            hex(RULE),
+            y = row,
+        );
+    }
+    for column in columns {
+        let _ = writeln!(
+            svg,
+            r#"<line x1="{x}" y1="0" x2="{x}" y2="{}" stroke="{}"/>"#,
+            grid.height(),
+            hex(RULE),
+            x = column,
+        );
+    }
+    for (label, (cx, cy)) in options.paper.labels(&grid) {
+        let _ = writeln!(
+            svg,
+            r#"<text x="{}" y="{}" fill="{}" style="font-size: {}px" text-anchor="middle" dominant-baseline="central">{}</text>"#,
+            cx,
+            cy,
+            hex(LABEL),
+            LABEL_SIZE * grid.line_height,
+            label
+        );
+    }
+
+    // Every character on its own, at the left of its cell
+    for y in grid.origin.1..grid.origin.1 + grid.rows {
+        for x in grid.origin.0..grid.origin.0 + grid.columns {
+            let Some(c) = memory.get(&Pos(x, y)).map(|cell| cell.read()) else {
+                continue;
+            };
+            if c.is_whitespace() {
+                continue;
+            }
+            let _ = writeln!(
+                svg,
+                r#"<text data-x="{}" data-y="{}" x="{}" y="{}">{}</text>"#,
+                x,
+                y,
+                grid.text_left(x),
+                grid.baseline(y),
+                escape(c)
+            );
+        }
+    }
+
+    for word in vm.get_circled_all() {
+        let ((cx, cy), rx, ry) = grid.ellipse(word);
+        let _ = writeln!(
+            svg,
+            r#"<ellipse cx="{}" cy="{}" rx="{}" ry="{}" fill="none" stroke="black" stroke-width="{}"/>"#,
+            cx,
+            cy,
+            rx,
+            ry,
+            options.scale / 16.
+        );
+    }
+
+    svg.push_str("</svg>\n");
+    Ok(svg)
+}
+
+/// Writes every sheet to `papier_<i>.svg` in the directory, in the order of
+/// [`collect_papers`].
+pub fn render_svgs<T: MemoryCell>(
+    root: PaperVM<T>,
+    dir: &Path,
+    options: &SvgOptions,
+) -> io::Result<()> {
+    for (i, paper) in collect_papers(root).into_iter().enumerate() {
+        let svg = render_svg(&paper, options)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        fs::write(dir.join(format!("papier_{}.svg", i)), svg)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,99 @@
+//! Animations written as GIF and APNG files.
+
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use imageproc::image::{codecs::gif::GifDecoder, AnimationDecoder};
+use papier::papervm::{instructions::*, CharCell, Instruction, PaperVM, CHARS_PER_FLOAT};
+use papier::programs::add_prog;
+use render_daan::{
+    animate::{animate, AnimationFormat, AnimationOptions},
+    render::{Paper, DEFAULT_FONT},
+};
+
+const CPFI: i64 = CHARS_PER_FLOAT as i64;
+
+/// Seven steps before the one that circles the result.
+fn writes() -> Vec<Instruction> {
+    let mut program: Vec<Instruction> = (1..=7).map(|i| write(i as f64)).collect();
+    program.push(circle((-CPFI, 0, CHARS_PER_FLOAT)));
+    program
+}
+
+fn options(every: usize, slide: usize) -> AnimationOptions<'static> {
+    AnimationOptions {
+        font: DEFAULT_FONT,
+        scale: 12.,
+        every,
+        delay: 100,
+        slide,
+        paper: Paper::default(),
+    }
+}
+
+fn out(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("render_daan_animate_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+fn apng_frames(path: &Path) -> u32 {
+    let decoder = png::Decoder::new(BufReader::new(File::open(path).unwrap()));
+    let reader = decoder.read_info().unwrap();
+    reader.info().animation_control.unwrap().num_frames
+}
+
+fn gif_frames(path: &Path) -> usize {
+    let decoder = GifDecoder::new(BufReader::new(File::open(path).unwrap())).unwrap();
+    decoder.into_frames().count()
+}
+
+#[test]
+fn frames_every_few_steps() {
+    // The first and the last frame, and one every `every` steps
+    for (every, frames) in [(1, 9), (2, 5), (3, 4), (10, 2)] {
+        let path = out(&format!("every_{}.png", every));
+        let vm = PaperVM::<CharCell>::new(writes());
+        animate(vm, &path, AnimationFormat::Apng, &options(every, 0)).unwrap();
+        assert_eq!(apng_frames(&path), frames, "every {}", every);
+        fs::remove_file(&path).unwrap();
+    }
+}
+
+#[test]
+fn gif_and_apng() {
+    // A call takes `slide` frames of its own, the steps on its sheet count
+    // like any other
+    let program = vec![
+        write(1.),
+        write(2.),
+        write("\n"),
+        call(
+            add_prog(),
+            vec![(0, -1, CHARS_PER_FLOAT), (CPFI, -1, CHARS_PER_FLOAT)],
+        ),
+        circle((-CPFI, 0, CHARS_PER_FLOAT)),
+    ];
+    // The called sheet circles its result in the step the caller goes on in
+    let frames = 1 + 3 + 3 + 2 + 1;
+
+    let gif = out("call.gif");
+    let vm = PaperVM::<CharCell>::new(program.clone());
+    animate(vm, &gif, AnimationFormat::Gif, &options(1, 3)).unwrap();
+    assert_eq!(gif_frames(&gif), frames);
+
+    let apng = out("call.png");
+    let vm = PaperVM::<CharCell>::new(program);
+    animate(vm, &apng, AnimationFormat::Apng, &options(1, 3)).unwrap();
+    assert_eq!(apng_frames(&apng), frames as u32);
+
+    assert_eq!(AnimationFormat::from_path(&gif), Some(AnimationFormat::Gif));
+    assert_eq!(
+        AnimationFormat::from_path(&apng),
+        Some(AnimationFormat::Apng)
+    );
+    assert_eq!(AnimationFormat::from_path(Path::new("a.svg")), None);
+    fs::remove_file(&gif).unwrap();
+    fs::remove_file(&apng).unwrap();
+}
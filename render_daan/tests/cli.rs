@@ -0,0 +1,99 @@
+//! The `render_daan` command, with the formats it writes and its options.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use imageproc::image::{self, Rgba};
+
+/// Finishes in its third step.
+const PROGRAM: &str = "write 1\nwrite 2\ncircle (-10, 0, 10)\n";
+
+/// An empty directory with the program in it, for the test to write to.
+fn dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("render_daan_cli_{}_{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("sum.papier"), PROGRAM).unwrap();
+    dir
+}
+
+fn render(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_render_daan"))
+        .arg(dir.join("sum.papier"))
+        .arg("--out")
+        .arg(dir)
+        .args(["--scale", "12"])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn formats() {
+    for (format, file) in [
+        ("png", "papier_0.png"),
+        ("annotated", "papier_0.png"),
+        ("svg", "papier_0.svg"),
+        ("pdf", "sum.pdf"),
+        ("tree", "papier_tree.png"),
+        ("gif", "papier.gif"),
+        ("apng", "papier.png"),
+    ] {
+        let dir = dir(format);
+        let output = render(&dir, &["--format", format]);
+        assert!(output.status.success(), "{}: {}", format, stderr(&output));
+        let mut written: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name != "sum.papier")
+            .collect();
+        written.sort();
+        assert_eq!(written, [file], "{}", format);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+#[test]
+fn paper_colour() {
+    let dir = dir("colour");
+    let output = render(&dir, &["--paper", "#1a2B3c"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let image = image::open(dir.join("papier_0.png")).unwrap().into_rgba8();
+    // In the margin
+    assert_eq!(*image.get_pixel(1, 1), Rgba([0x1a, 0x2b, 0x3c, 255]));
+
+    for colour in [
+        "1a2b3c", "#1a2b3", "#1a2b3c4", "#1a2g3c", "#+1+2+3", "#é1234",
+    ] {
+        let output = render(&dir, &["--paper", colour]);
+        assert!(!output.status.success(), "{}", colour);
+        assert!(
+            stderr(&output).contains("is not a colour like #rrggbb"),
+            "{}: {}",
+            colour,
+            stderr(&output)
+        );
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn step_limit() {
+    let dir = dir("steps");
+    // The step that finishes is the last one allowed
+    let output = render(&dir, &["--max-steps", "3"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = render(&dir, &["--max-steps", "2"]);
+    assert!(!output.status.success());
+    assert_eq!(
+        stderr(&output),
+        "error: not finished after the step limit of 2\n"
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
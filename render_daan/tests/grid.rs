@@ -0,0 +1,203 @@
+//! Cells, circles and paper of sheets drawn in the built-in font.
+
+use ab_glyph::{FontRef, PxScale};
+use imageproc::image::{Rgba, RgbaImage};
+use papier::papervm::{instructions::*, CharCell, PaperVM, Pos, Word, CHARS_PER_FLOAT};
+use render_daan::{
+    grid::{bounds, Grid},
+    render::{render_paper, Lines, Paper, DEFAULT_FONT, LABEL, RULE},
+};
+
+const SCALE: f32 = 48.;
+
+fn font() -> FontRef<'static> {
+    FontRef::try_from_slice(DEFAULT_FONT).unwrap()
+}
+
+fn grid() -> Grid {
+    Grid::from_bounds(Pos(0, 0), Pos(30, 4), &font(), PxScale::from(SCALE), SCALE)
+}
+
+/// Below 1 inside the ellipse, 1 on it and above 1 outside.
+fn ellipse_at(grid: &Grid, word: Word, (x, y): (f32, f32)) -> f32 {
+    let ((cx, cy), rx, ry) = grid.ellipse(word);
+    ((x - cx) / rx).powi(2) + ((y - cy) / ry).powi(2)
+}
+
+fn corners(grid: &Grid, Pos(x, y): Pos) -> [(f32, f32); 4] {
+    let (left, top) = (grid.left(x), grid.top(y));
+    let (right, bottom) = (left + grid.cell_width, top + grid.line_height);
+    [(left, top), (right, top), (left, bottom), (right, bottom)]
+}
+
+fn centre(grid: &Grid, Pos(x, y): Pos) -> (f32, f32) {
+    (
+        grid.left(x) + grid.cell_width / 2.,
+        grid.top(y) + grid.line_height / 2.,
+    )
+}
+
+#[test]
+fn ellipse_contains_the_word() {
+    let grid = grid();
+    for len in 1..=CHARS_PER_FLOAT {
+        let word = Word(Pos(10, 2), len);
+        for x in 10..10 + len as i64 {
+            assert!(ellipse_at(&grid, word, centre(&grid, Pos(x, 2))) < 1.);
+            for corner in corners(&grid, Pos(x, 2)) {
+                assert!(ellipse_at(&grid, word, corner) <= 1. + 1e-4, "{}", len);
+            }
+        }
+        // It goes through the corners of the word
+        let end = Pos(10 + len as i64 - 1, 2);
+        let on = ellipse_at(&grid, word, corners(&grid, end)[3]);
+        assert!((on - 1.).abs() < 1e-4, "{}: {}", len, on);
+    }
+}
+
+#[test]
+fn ellipse_leaves_out_neighbours() {
+    let grid = grid();
+    for len in 1..=CHARS_PER_FLOAT {
+        let word = Word(Pos(10, 2), len);
+        let end = 10 + len as i64;
+        // Words on the rows above and below stay outside
+        for x in 9..=end {
+            for y in [1, 3] {
+                let at = ellipse_at(&grid, word, centre(&grid, Pos(x, y)));
+                assert!(at > 1., "{} at ({}, {}): {}", len, x, y, at);
+            }
+        }
+        // The ellipse reaches into the cells before and after the word, but
+        // not around them
+        for x in [9, end] {
+            let outside = corners(&grid, Pos(x, 2))
+                .into_iter()
+                .any(|corner| ellipse_at(&grid, word, corner) > 1.);
+            assert!(outside, "{} at {}", len, x);
+        }
+    }
+}
+
+#[test]
+fn sheet_without_circle() {
+    let font = font();
+    let paper = Paper::default();
+
+    // Still running, nothing circled yet
+    let mut vm = PaperVM::<CharCell>::new(vec![write(12.), circle((-10, 0, CHARS_PER_FLOAT))]);
+    vm.step();
+    assert!(!vm.is_finished());
+    assert_eq!(bounds(&vm), Some((Pos(0, 0), Pos(9, 0))));
+    let image = render_paper(&vm, &font, PxScale::from(SCALE), &paper);
+    let grid = paper.grid(&vm, &font, PxScale::from(SCALE));
+    assert_eq!(image.width(), grid.width().ceil() as u32);
+    assert_eq!(image.height(), grid.height().ceil() as u32);
+
+    // Nothing written at all
+    let vm = PaperVM::<CharCell>::new(vec![]);
+    assert_eq!(bounds(&vm), None);
+    render_paper(&vm, &font, PxScale::from(SCALE), &paper);
+}
+
+/// `12` and `3` on two rows.
+fn two_rows() -> PaperVM<CharCell> {
+    let mut vm = PaperVM::<CharCell>::new(vec![]);
+    vm.write(&12.);
+    vm.write(&"\n");
+    vm.write(&3.);
+    vm
+}
+
+fn drawn(paper: &Paper) -> (Grid, RgbaImage) {
+    let vm = two_rows();
+    let font = font();
+    let scale = PxScale::from(SCALE);
+    (
+        paper.grid(&vm, &font, scale),
+        render_paper(&vm, &font, scale, paper),
+    )
+}
+
+fn count(image: &RgbaImage, colour: Rgba<u8>, xs: impl Iterator<Item = u32>, y: u32) -> usize {
+    xs.filter(|&x| *image.get_pixel(x, y) == colour).count()
+}
+
+#[test]
+fn squared_paper() {
+    let paper = Paper {
+        lines: Lines::Squared,
+        ..Paper::default()
+    };
+    let (grid, image) = drawn(&paper);
+    assert_eq!(grid.cell_width, grid.line_height);
+    let plain = drawn(&Paper::default()).0;
+    assert!(grid.cell_width >= plain.cell_width && grid.line_height >= plain.line_height);
+
+    // A rule on every side of every cell, and none inside them. Halfway
+    // through the top margin there is no writing in the way.
+    let mid = (grid.margin / 2.) as u32;
+    for x in 0..grid.columns {
+        let (left, right) = (grid.left(x).round(), grid.left(x + 1).round());
+        assert_eq!(*image.get_pixel(left as u32, mid), RULE, "column {}", x);
+        let inside = left as u32 + 2..right as u32 - 1;
+        assert_eq!(count(&image, RULE, inside, mid), 0, "column {}", x);
+    }
+    let left = (grid.margin / 2.) as u32;
+    for y in 0..=grid.rows {
+        assert_eq!(*image.get_pixel(left, grid.top(y).round() as u32), RULE);
+    }
+    // Over the margins as well
+    assert_eq!(*image.get_pixel(left, grid.top(-1).round() as u32), RULE);
+
+    // Ruled paper has no columns
+    let paper = Paper {
+        lines: Lines::Ruled,
+        ..Paper::default()
+    };
+    let (grid, image) = drawn(&paper);
+    assert_eq!(count(&image, RULE, 0..image.width(), mid), 0);
+    assert_eq!(*image.get_pixel(left, grid.top(1).round() as u32), RULE);
+}
+
+#[test]
+fn labels_in_the_margins() {
+    let paper = Paper {
+        labels: true,
+        ..Paper::default()
+    };
+    let (grid, image) = drawn(&paper);
+    let labels = paper.labels(&grid);
+    assert!(labels.iter().any(|(label, _)| label == "0"));
+    assert!(labels.iter().any(|(label, _)| label == "1"));
+
+    // Drawn around their centres, in the colour of the labels blended into
+    // the paper
+    let margin = grid.margin as u32;
+    let in_margin = |x: u32, y: u32| x < margin || y < margin;
+    let half = (grid.cell_width / 2.) as u32;
+    for (label, (cx, cy)) in &labels {
+        let (cx, cy) = (*cx as u32, *cy as u32);
+        let drawn = (cx.saturating_sub(half)..cx + half)
+            .flat_map(|x| (cy.saturating_sub(half)..cy + half).map(move |y| (x, y)))
+            .any(|(x, y)| *image.get_pixel(x, y) != paper.colour);
+        assert!(drawn, "{} at ({}, {})", label, cx, cy);
+    }
+    for (x, y, pixel) in image.enumerate_pixels() {
+        if in_margin(x, y) && *pixel != paper.colour {
+            assert!(pixel[2] >= pixel[0], "{:?} at ({}, {})", pixel, x, y);
+        }
+    }
+    assert!(image
+        .pixels()
+        .any(|pixel| pixel[2].saturating_sub(pixel[0]) > (LABEL[2] - LABEL[0]) / 2));
+
+    // Nothing in the margins without them
+    let image = drawn(&Paper::default()).1;
+    for (x, y, pixel) in image.enumerate_pixels() {
+        if in_margin(x, y) {
+            assert_eq!(*pixel, Paper::default().colour, "({}, {})", x, y);
+        }
+    }
+    assert!(Paper::default().labels(&grid).is_empty());
+}
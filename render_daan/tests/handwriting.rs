@@ -0,0 +1,66 @@
+//! Names of glyph images and the dice that place handwritten characters.
+
+use std::path::Path;
+
+use render_daan::handwriting::{glyph_name, Dice};
+
+fn name(file: &str) -> Option<char> {
+    glyph_name(Path::new(file))
+}
+
+#[test]
+fn glyph_names() {
+    assert_eq!(name("a.png"), Some('a'));
+    assert_eq!(name("a_2.png"), Some('a'));
+    assert_eq!(name("dir/7_left.png"), Some('7'));
+    // An underscore on its own, or with a variant after it
+    assert_eq!(name("_.png"), Some('_'));
+    assert_eq!(name("__2.png"), Some('_'));
+    assert_eq!(name("U+2F.png"), Some('/'));
+    assert_eq!(name("U+2F_1.png"), Some('/'));
+    assert_eq!(name("u+2f.png"), Some('/'));
+}
+
+#[test]
+fn bad_glyph_names() {
+    assert_eq!(name("ab.png"), None);
+    assert_eq!(name("ab_1.png"), None);
+    assert_eq!(name("U+.png"), None);
+    assert_eq!(name("U+XYZ.png"), None);
+    // Surrogates are not characters
+    assert_eq!(name("U+D800.png"), None);
+}
+
+fn rolls(x: i64, y: i64, c: char) -> Vec<u64> {
+    let mut dice = Dice::new(x, y, c);
+    (0..8).map(|_| dice.roll()).collect()
+}
+
+#[test]
+fn dice_are_fixed() {
+    // The same on every run, so a sheet is always written the same way
+    assert_eq!(
+        rolls(3, -2, 'a')[..2],
+        [0x7a9f833f5cf61fa3, 0xc038ca68a047fa9a]
+    );
+    assert_eq!(rolls(3, -2, 'a'), rolls(3, -2, 'a'));
+
+    for other in [
+        rolls(2, -2, 'a'),
+        rolls(4, -2, 'a'),
+        rolls(3, -1, 'a'),
+        rolls(3, -3, 'a'),
+        rolls(3, -2, 'b'),
+    ] {
+        assert_ne!(rolls(3, -2, 'a'), other);
+    }
+}
+
+#[test]
+fn dice_ranges() {
+    let mut dice = Dice::new(0, 0, ' ');
+    for _ in 0..1000 {
+        assert!((0. ..1.).contains(&dice.unit()));
+        assert!((-2. ..=2.).contains(&dice.spread(2.)));
+    }
+}
@@ -0,0 +1,53 @@
+//! The picture of a run with the sheets of its calls below their callers.
+
+use ab_glyph::{FontRef, PxScale};
+use papier::papervm::{instructions::*, CharCell, PaperVM, Word, CHARS_PER_FLOAT};
+use papier::programs::add_prog;
+use render_daan::{
+    overview::render_overview,
+    render::{Paper, DEFAULT_FONT, DESK},
+};
+
+const SCALE: f32 = 24.;
+const CPFI: i64 = CHARS_PER_FLOAT as i64;
+
+#[test]
+fn nested_calls() {
+    // The sheet calls one that calls `add_prog` in turn
+    let inner = vec![
+        write(3.),
+        write(4.),
+        call(
+            add_prog(),
+            vec![(-2 * CPFI, 0, CHARS_PER_FLOAT), (-CPFI, 0, CHARS_PER_FLOAT)],
+        ),
+        circle_all(Vec::<Word>::new()),
+    ];
+    let program = vec![
+        write(1.),
+        call(inner, Vec::<Word>::new()),
+        circle_all(Vec::<Word>::new()),
+    ];
+    let mut vm = PaperVM::<CharCell>::new(program);
+    while !vm.step().is_finished() {}
+    let middle = &vm.finished_papers[0];
+    let sheets = [&vm, middle, &middle.finished_papers[0]];
+
+    let font = FontRef::try_from_slice(DEFAULT_FONT).unwrap();
+    let scale = PxScale::from(SCALE);
+    let paper = Paper::default();
+    let image = render_overview(&vm, &font, scale, &paper);
+
+    // Every sheet has a row of its own, and they don't overlap
+    let grids = sheets.map(|sheet| paper.grid(sheet, &font, scale));
+    let widest = grids.iter().map(|grid| grid.width().ceil() as u32).max();
+    assert!(image.width() > widest.unwrap());
+    let heights: u32 = grids.iter().map(|grid| grid.height().ceil() as u32).sum();
+    assert!(image.height() > heights);
+    let area: u32 = grids
+        .iter()
+        .map(|grid| grid.width().ceil() as u32 * grid.height().ceil() as u32)
+        .sum();
+    let drawn = image.pixels().filter(|&&pixel| pixel != DESK).count();
+    assert!(drawn as u32 >= area, "{} of {}", drawn, area);
+}
@@ -0,0 +1,61 @@
+//! PDFs with a page for every sheet of a run.
+
+use std::fs;
+
+use papier::papervm::{instructions::*, CharCell, Instruction, PaperVM, Word, CHARS_PER_FLOAT};
+use papier::programs::add_prog;
+use render_daan::{
+    pdf::{render_pdf, PdfOptions},
+    render::{Paper, DEFAULT_FONT},
+};
+
+const CPFI: i64 = CHARS_PER_FLOAT as i64;
+
+/// Adds the two numbers in front of the cursor by calling `add_prog`.
+fn add_call() -> Instruction {
+    call(
+        add_prog(),
+        vec![(-2 * CPFI, 0, CHARS_PER_FLOAT), (-CPFI, 0, CHARS_PER_FLOAT)],
+    )
+}
+
+/// The number of pages in a PDF, counted by their page objects.
+fn pages(pdf: &[u8]) -> usize {
+    let pdf = String::from_utf8_lossy(pdf);
+    pdf.matches("/Type/Page").count() - pdf.matches("/Type/Pages").count()
+}
+
+#[test]
+fn page_per_sheet() {
+    // Two calls from the sheet, and one from inside the second
+    let nested = vec![
+        write(3.),
+        write(4.),
+        add_call(),
+        circle_all(Vec::<Word>::new()),
+    ];
+    let program = vec![
+        write(1.),
+        write(2.),
+        add_call(),
+        call(nested, Vec::<Word>::new()),
+        circle_all(Vec::<Word>::new()),
+    ];
+    let mut vm = PaperVM::<CharCell>::new(program);
+    while !vm.step().is_finished() {}
+    assert_eq!(vm.finished_papers.len(), 2);
+    assert_eq!(vm.finished_papers[1].finished_papers.len(), 1);
+
+    let path = std::env::temp_dir().join(format!("render_daan_pdf_{}.pdf", std::process::id()));
+    let options = PdfOptions {
+        font: DEFAULT_FONT,
+        size: 12.,
+        paper: Paper::default(),
+    };
+    render_pdf(&vm, "calls", &path, &options).unwrap();
+    let pdf = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(pdf.starts_with(b"%PDF"));
+    // The table of contents and four sheets
+    assert_eq!(pages(&pdf), 1 + 4);
+}
@@ -0,0 +1,80 @@
+//! Sheets drawn as SVG documents.
+
+use ab_glyph::{FontRef, PxScale};
+use papier::papervm::{instructions::*, CharCell, MemoryCell, PaperVM, CHARS_PER_FLOAT};
+use render_daan::{
+    render::{Lines, Paper, DEFAULT_FONT},
+    svg::{render_svg, SvgOptions},
+};
+
+const CPFI: i64 = CHARS_PER_FLOAT as i64;
+
+fn options(paper: Paper) -> SvgOptions<'static> {
+    SvgOptions {
+        font: DEFAULT_FONT,
+        scale: 48.,
+        paper,
+    }
+}
+
+fn sheet() -> PaperVM<CharCell> {
+    let mut vm = PaperVM::new(vec![
+        write("\n"),
+        write("a<b & c"),
+        add((0, -1, CHARS_PER_FLOAT), (CPFI, -1, CHARS_PER_FLOAT)),
+        circle((-CPFI, 0, CHARS_PER_FLOAT)),
+    ]);
+    vm.write(&12.);
+    vm.write(&30.);
+    while !vm.step().is_finished() {}
+    vm
+}
+
+/// The value of the first `name="..."` attribute.
+fn attribute<'a>(svg: &'a str, name: &str) -> &'a str {
+    let start = svg.find(&format!(" {}=\"", name)).unwrap() + name.len() + 3;
+    let end = start + svg[start..].find('"').unwrap();
+    &svg[start..end]
+}
+
+#[test]
+fn one_text_per_written_cell() {
+    let vm = sheet();
+    let options = options(Paper {
+        labels: true,
+        ..Paper::default()
+    });
+    let svg = render_svg(&vm, &options).unwrap();
+
+    let written = vm
+        .get_memory()
+        .values()
+        .filter(|cell| !cell.read().is_whitespace())
+        .count();
+    assert_eq!(svg.matches("<text data-x=").count(), written);
+    assert!(svg.contains(r#"<text data-x="0" data-y="0" "#));
+    assert!(svg.contains(">&lt;</text>"));
+    assert!(svg.contains(">&amp;</text>"));
+    assert_eq!(svg.matches("<ellipse ").count(), 1);
+}
+
+#[test]
+fn sheet_dimensions() {
+    let vm = sheet();
+    for lines in [Lines::Blank, Lines::Ruled, Lines::Squared] {
+        let paper = Paper {
+            lines,
+            ..Paper::default()
+        };
+        let svg = render_svg(&vm, &options(paper)).unwrap();
+        let font = FontRef::try_from_slice(DEFAULT_FONT).unwrap();
+        let grid = paper.grid(&vm, &font, PxScale::from(48.));
+
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(attribute(&svg, "width"), grid.width().to_string());
+        assert_eq!(attribute(&svg, "height"), grid.height().to_string());
+        assert_eq!(attribute(&svg, "columns"), "20");
+        assert_eq!(attribute(&svg, "rows"), "2");
+    }
+}
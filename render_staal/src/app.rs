@@ -1,30 +1,21 @@
 use crossterm::event::KeyCode;
-use papier::convenience::*;
 use papier::papervm::Instruction;
 use papier::papervm::*;
-use papier::programs::*;
-use ratatui::{
-    layout::Rect,
-    style::{Color, Style, Stylize},
-    text::{Line, Span},
-    widgets::ScrollbarState,
-};
-use std::{
-    collections::HashMap,
-    error::{self, Error},
-    fs::{create_dir_all, File},
-    io::Write,
-    path::Path,
-};
+use ratatui::layout::Rect;
+use std::error::{self, Error};
 
 pub type AppResult<T> = std::result::Result<T, Box<dyn error::Error>>;
 
+/// Describes where the instruction at a [`SimStepState::trace`] came from.
+pub type SourceLookup = Box<dyn Fn(&[usize]) -> Option<String>>;
+
 pub struct App {
     pub running: bool,
     last_sim_step: SimStepState,
     free_running: bool,
     vm: PaperVM<CharCell>,
     view_pos: Pos,
+    source: Option<SourceLookup>,
 }
 
 impl App {
@@ -41,9 +32,16 @@ impl App {
             free_running: false,
             vm,
             view_pos: Pos(0, 0),
+            source: None,
         }
     }
 
+    /// Shows the source of every instruction next to it.
+    pub fn with_source(mut self, source: SourceLookup) -> Self {
+        self.source = Some(source);
+        self
+    }
+
     pub async fn init(&mut self) -> Result<(), Box<dyn Error>> {
         Ok(())
     }
@@ -70,7 +68,7 @@ impl App {
         }
     }
 
-    pub fn resize(&mut self, width: u16, height: u16) {}
+    pub fn resize(&mut self, _width: u16, _height: u16) {}
 
     pub fn scroll(&mut self, key: KeyCode) {
         match key {
@@ -86,8 +84,17 @@ impl App {
         self.last_sim_step.instruction.clone()
     }
 
-    pub fn get_view_as_string(&self, size: Rect) -> String {
-        let memory = self.vm.lowest_subroutine().get_memory();
+    pub fn current_source(&self) -> Option<String> {
+        (self.source.as_ref()?)(&self.last_sim_step.trace)
+    }
+
+    /// The sheets currently being worked on, shown side by side.
+    pub fn sheets(&self) -> Vec<&PaperVM<CharCell>> {
+        self.vm.lowest_subroutines()
+    }
+
+    pub fn get_view_as_string(&self, sheet: &PaperVM<CharCell>, size: Rect) -> String {
+        let memory = sheet.get_memory();
 
         let mut result = String::new();
         for y in self.view_pos.1..(self.view_pos.1 + size.height as i64) {
@@ -110,24 +117,30 @@ impl App {
     pub fn last_cursor(&self) -> Pos {
         self.apply_view(self.last_sim_step.cursor)
     }
-    pub fn cursor(&self) -> Pos {
-        self.apply_view(self.vm.lowest_subroutine().cursor())
-        // self.apply_view(self.last_sim_step.cursor)
+
+    pub fn cursor(&self, sheet: &PaperVM<CharCell>) -> Pos {
+        self.apply_view(sheet.cursor())
     }
 
     pub fn highlight_words(&self) -> Vec<Word> {
         match self.current_instruction() {
             Instruction::Circle(word) => vec![word],
+            Instruction::CircleAll(words) => words,
             Instruction::Add(w1, w2) => vec![w1, w2],
             Instruction::Sub(w1, w2) => vec![w1, w2],
             Instruction::Mod(w1, w2) => vec![w1, w2],
+            Instruction::Mul(w1, w2) => vec![w1, w2],
+            Instruction::Div(w1, w2) => vec![w1, w2],
             Instruction::Copy(word) => vec![word],
             Instruction::TrimmedCopy(word) => vec![word],
             Instruction::Write(_) => vec![],
             Instruction::Call(_, _) => vec![],
+            Instruction::Fork(_) => vec![],
+            Instruction::Join => vec![],
             Instruction::Jump(_) => vec![],
             Instruction::JumpRelIf(word, _, _, _) => vec![word],
             Instruction::JumpRelIfStr(word, _, _) => vec![word],
+            Instruction::JumpRelIfCond(condition, _) => condition.words(),
             Instruction::MoveCursor(_) => vec![],
             Instruction::Stop => vec![],
             Instruction::BreakPoint => vec![],
@@ -1,4 +1,4 @@
-use crate::app::{App, AppResult};
+use crate::app::{App, AppResult, SourceLookup};
 use crate::event::{Event, EventHandler};
 use crate::handler::{handle_key_events, handle_mouse_events};
 use crate::tui::Tui;
@@ -14,13 +14,20 @@ pub mod tui;
 pub mod ui;
 
 pub fn run_program(program: Vec<Instruction>) -> AppResult<()> {
+    run_app(App::new(program))
+}
+
+/// Runs the program with the source of the current instruction in the title.
+pub fn run_program_with_source(program: Vec<Instruction>, source: SourceLookup) -> AppResult<()> {
+    run_app(App::new(program).with_source(source))
+}
+
+fn run_app(mut app: App) -> AppResult<()> {
     tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()
         .unwrap()
         .block_on(async move {
-            let mut app = App::new(program);
-
             let backend = CrosstermBackend::new(io::stderr());
             let terminal = Terminal::new(backend)?;
             let events = EventHandler::new(10);
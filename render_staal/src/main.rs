@@ -1,13 +1,7 @@
 use papier::convenience::*;
 use papier::papervm::*;
 use papier::programs::*;
-use ratatui::backend::CrosstermBackend;
-use ratatui::Terminal;
-use render_staal::app::{App, AppResult};
-use render_staal::event::{Event, EventHandler};
-use render_staal::handler::{handle_key_events, handle_mouse_events};
-use render_staal::tui::Tui;
-use std::io;
+use render_staal::app::AppResult;
 
 pub fn main() -> AppResult<()> {
     let program = call_static(